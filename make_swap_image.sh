@@ -0,0 +1,14 @@
+#!/bin/sh
+# Makes the empty disk image QEMU attaches for swap, run it once before cargo run or cargo test.
+# An image of the wrong size is made again, which also throws away what was on it
+set -e
+cd "$(dirname "$0")"
+
+image=swap.img
+size=$((16 * 1024 * 1024))
+
+if [ ! -f "$image" ] || [ "$(wc -c < "$image")" -ne "$size" ]; then
+    rm -f "$image"
+    dd if=/dev/zero of="$image" bs=1 count=0 seek="$size" 2> /dev/null
+    echo "created $image"
+fi
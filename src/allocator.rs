@@ -8,44 +8,151 @@
 
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, FrameDeallocator, Mapper, Page, Size4KiB,
     },
     VirtAddr,
 };
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::{change_fg, lock, print, println, memory::{self, GlobalFrameAllocator, mapping::MapFlags}};
+use alloc::{alloc::Layout, boxed::Box, vec::Vec};
+use vga::colors::Color16;
 
-use bump::BumpAllocator;
-use linked_list::LinkedListAllocator;
-use fixed_size_block::FixedSizeBlockAllocator;
+use fixed_size_block::BLOCK_SIZES;
 
 pub mod bump;
 pub mod linked_list;
 pub mod fixed_size_block;
+pub mod slab;
+#[cfg(feature = "heap-debug")]
+pub mod debug;
 
+/*********************************************************
+* The global allocator is picked at build time with the
+	alloc-bump, alloc-linked-list, alloc-slab or
+	alloc-fixed-size-block features, fixed size block is
+	the default. Exactly one of them has to be enabled, so
+	the others need --no-default-features.
+*********************************************************/
+#[cfg(not(any(
+    feature = "alloc-bump",
+    feature = "alloc-linked-list",
+    feature = "alloc-slab",
+    feature = "alloc-fixed-size-block",
+)))]
+compile_error!("one of the alloc-bump, alloc-linked-list, alloc-slab and alloc-fixed-size-block features has to be enabled");
+
+#[cfg(any(
+    all(feature = "alloc-bump", feature = "alloc-linked-list"),
+    all(feature = "alloc-bump", feature = "alloc-slab"),
+    all(feature = "alloc-bump", feature = "alloc-fixed-size-block"),
+    all(feature = "alloc-linked-list", feature = "alloc-slab"),
+    all(feature = "alloc-linked-list", feature = "alloc-fixed-size-block"),
+    all(feature = "alloc-slab", feature = "alloc-fixed-size-block"),
+))]
+compile_error!("only one of the alloc-bump, alloc-linked-list, alloc-slab and alloc-fixed-size-block features can be enabled, use --no-default-features to pick another one");
+
+#[cfg(feature = "alloc-bump")]
+type GlobalHeap = bump::BumpAllocator;
+
+#[cfg(feature = "alloc-linked-list")]
+type GlobalHeap = linked_list::LinkedListAllocator;
+
+#[cfg(feature = "alloc-slab")]
+type GlobalHeap = slab::SlabAllocator;
+
+#[cfg(feature = "alloc-fixed-size-block")]
+type GlobalHeap = fixed_size_block::FixedSizeBlockAllocator;
+
+#[cfg_attr(not(feature = "heap-debug"), global_allocator)]
+static ALLOCATOR: Locked<GlobalHeap> = Locked::new(GlobalHeap::new());
+
+/*********************************************************
+* With heap-debug every allocation goes through the checks
+	in `debug` before it reaches the chosen allocator
+*********************************************************/
+#[cfg(feature = "heap-debug")]
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+static DEBUG_ALLOCATOR: debug::DebugAllocator<Locked<GlobalHeap>> = debug::DebugAllocator::new(&ALLOCATOR);
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB, mapped by init_heap
+pub const HEAP_MAX_SIZE: usize = 32 * 1024 * 1024; // default limit for growing the heap
+
+const HEAP_GROW_STEP: usize = 64 * 1024;
+
+static HEAP_MAPPED: AtomicUsize = AtomicUsize::new(0);
+static HEAP_LIMIT: AtomicUsize = AtomicUsize::new(HEAP_MAX_SIZE);
+static LAST_GROW_ERROR: lock::Mutex<Option<GrowError>> = lock::Mutex::new(None);
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub start: usize,
+    pub size: usize,
+    pub limit: usize,
+}
+
+/*********************************************
+* Returned by the fallible allocation helpers
+*********************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError {
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrowError {
+    NotInitialized,
+    NotKernelHeap,
+    LimitReached,
+    OutOfFrames,
+}
+
+/*********************************************************
+* Common numbers for all heap allocators, `free_blocks`
+	is only filled in by the fixed size block allocator
+*********************************************************/
+#[derive(Debug, Clone, Copy)]
+pub struct AllocatorStats {
+    pub name: &'static str,
+    pub allocations: usize,
+    pub total_allocations: usize,
+    pub bytes_in_use: usize,
+    pub heap_size: usize,
+    pub heap_free: usize,
+    pub largest_free_block: usize,
+    pub free_blocks: Option<[usize; BLOCK_SIZES.len()]>,
+}
+
+/*********************************************************
+* Implemented by every allocator that can back the
+	kernel heap
+*********************************************************/
+pub trait HeapAllocator {
+    const NAME: &'static str;
+
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize);
+    fn stats(&mut self) -> AllocatorStats;
+}
 
 /***********************************
 * functions for easy heap allocation
 ***********************************/
 
 /**************************************************************
-* A wrapper around spin::Mutex to permit trait implementations.
+* A wrapper around lock::Mutex to permit trait implementations.
 **************************************************************/ 
 pub struct Locked<A> {
-    inner: spin::Mutex<A>,
+    inner: lock::Mutex<A>,
 }
 
 impl<A> Locked<A> {
     pub const fn new(inner: A) -> Self {
         Locked {
-            inner: spin::Mutex::new(inner),
+            inner: lock::Mutex::new(inner),
         }
     }
 
-    pub fn lock(&self) -> spin::MutexGuard<A> {
+    pub fn lock(&self) -> lock::MutexGuard<A> {
         self.inner.lock()
     }
 }
@@ -66,10 +173,26 @@ fn q_align_up(addr: usize, align: usize) -> usize {
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    map_heap_pages(HEAP_START, HEAP_SIZE, mapper, frame_allocator)?;
+
+    unsafe {
+        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+    HEAP_MAPPED.store(HEAP_SIZE, Ordering::SeqCst);
+
+    Ok(())
+}
+
+fn map_heap_pages(
+    start: usize,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
     let page_range = {
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_start = VirtAddr::new(start as u64);
+        let heap_end = heap_start + size - 1u64;
         let heap_start_page = Page::containing_address(heap_start);
         let heap_end_page = Page::containing_address(heap_end);
         Page::range_inclusive(heap_start_page, heap_end_page)
@@ -79,15 +202,202 @@ pub fn init_heap(
         let frame = frame_allocator
             .allocate_frame()
             .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let flags = MapFlags::KERNEL_DATA.page_table_flags();
         unsafe {
             mapper.map_to(page, frame, flags, frame_allocator)?.flush()
         };
     }
 
+    Ok(())
+}
+
+/**************************************************************
+* Maps at least `min_size` more bytes right after the end of
+	the heap and returns how many were added. Called by the
+	allocator with its lock held, so nothing here may allocate.
+* `heap_end` is where the calling allocator's memory ends, only
+	an allocator managing the kernel heap may grow it
+**************************************************************/
+pub(crate) fn grow_heap(heap_end: usize, min_size: usize) -> Result<usize, GrowError> {
+    let result = map_more_heap(heap_end, min_size);
+    match result {
+        Ok(_) => *LAST_GROW_ERROR.lock() = None,
+        // an allocator over some other memory, nothing to report
+        Err(GrowError::NotKernelHeap) => {}
+        // reported by the alloc error handler, if nobody handles the null
+        Err(error) => *LAST_GROW_ERROR.lock() = Some(error),
+    }
+
+    result
+}
+
+fn map_more_heap(heap_end: usize, min_size: usize) -> Result<usize, GrowError> {
+    let mapped = HEAP_MAPPED.load(Ordering::SeqCst);
+    if mapped == 0 {
+        return Err(GrowError::NotInitialized);
+    }
+    if heap_end != HEAP_START + mapped {
+        return Err(GrowError::NotKernelHeap);
+    }
+
+    let available = HEAP_LIMIT.load(Ordering::SeqCst).saturating_sub(mapped);
+    let by = q_align_up(min_size.max(HEAP_GROW_STEP), 4096).min(available);
+    if by < min_size {
+        return Err(GrowError::LimitReached);
+    }
+
+    /*************************************************
+    * map page by page so a partial success still
+    	counts, the allocator can use what we got as
+    	long as it's enough for the allocation
+    *************************************************/
+    let mut mapper = unsafe { memory::kernel_mapper() };
+    let mut grown = 0;
+    while grown < by {
+        if map_heap_pages(HEAP_START + mapped + grown, 4096, &mut mapper, &mut GlobalFrameAllocator).is_err() {
+            break;
+        }
+        grown += 4096;
+    }
+
+    // not enough -> give the pages back, the heap stays where the allocator thinks it ends
+    if grown < min_size {
+        unmap_heap_pages(HEAP_START + mapped, grown, &mut mapper);
+        return Err(GrowError::OutOfFrames);
+    }
+
+    HEAP_MAPPED.store(mapped + grown, Ordering::SeqCst);
+    Ok(grown)
+}
+
+fn unmap_heap_pages(start: usize, size: usize, mapper: &mut impl Mapper<Size4KiB>) {
+    for offset in (0..size).step_by(4096) {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new((start + offset) as u64));
+        if let Ok((frame, flush)) = mapper.unmap(page) {
+            flush.flush();
+            unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+        }
+    }
+}
+
+/*********************************************************
+* Printed by the alloc error handler before the kernel
+	panics, so the numbers are from right after the failure
+*********************************************************/
+pub fn report_out_of_memory(layout: Layout) {
+    let heap = heap_stats();
+    let allocator = allocator_stats();
+
+    change_fg!(Color16::Red);
+    println!("OUT OF MEMORY: can't allocate {} bytes (align {})", layout.size(), layout.align());
+    match *LAST_GROW_ERROR.lock() {
+        Some(GrowError::NotInitialized) => println!("The heap isn't initialized yet"),
+        Some(GrowError::NotKernelHeap) => {}
+        Some(GrowError::LimitReached) => println!("The heap reached its limit of {} KiB", heap.limit / 1024),
+        Some(GrowError::OutOfFrames) => println!("No physical memory left to grow the heap"),
+        None => {}
+    }
+    change_fg!(Color16::White);
+
+    println!("Heap: {} KiB mapped of {} KiB limit", heap.size / 1024, heap.limit / 1024);
+    println!("{} allocator: {} bytes free of {}, largest free block {} bytes",
+        allocator.name, allocator.heap_free, allocator.heap_size, allocator.largest_free_block);
+
+    if let Some(free_blocks) = allocator.free_blocks {
+        print!("Free blocks per size:");
+        for (size, count) in BLOCK_SIZES.iter().zip(free_blocks.iter()) {
+            print!(" {}B={}", size, count);
+        }
+        println!();
+    }
+}
+
+/*******************************************************************
+* Allocate without ending up in the alloc error handler, for kernel
+	code that can do with less memory if it has to
+*******************************************************************/
+pub fn try_box<T>(value: T) -> Result<Box<T>, AllocError> {
+    let layout = Layout::new::<T>();
+    if layout.size() == 0 {
+        return Ok(Box::new(value));
+    }
+
+    let ptr = unsafe { alloc::alloc::alloc(layout) } as *mut T;
+    if ptr.is_null() {
+        return Err(AllocError { layout });
+    }
+
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+        ptr.write(value);
+        Ok(Box::from_raw(ptr))
     }
+}
 
-    Ok(())
-}
\ No newline at end of file
+pub fn try_vec<T>(capacity: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity).map_err(|_| AllocError {
+        layout: Layout::array::<T>(capacity).unwrap_or(Layout::new::<T>()),
+    })?;
+
+    Ok(vec)
+}
+
+/*****************************************************************
+* A linked_list_allocator heap doesn't show its holes, so binary
+	search for the biggest allocation that still fits. Every probe
+	is freed right away, the heap isn't grown for it.
+*****************************************************************/
+pub(crate) fn largest_free_block(heap: &mut linked_list_allocator::Heap) -> usize {
+    let align = core::mem::size_of::<usize>();
+    let (mut low, mut high) = (0, heap.free() / align);
+
+    while low < high {
+        let mid = (low + high + 1) / 2;
+        let layout = Layout::from_size_align(mid * align, align).unwrap();
+        match heap.allocate_first_fit(layout) {
+            Ok(ptr) => {
+                unsafe { heap.deallocate(ptr, layout) };
+                low = mid;
+            }
+            Err(_) => high = mid - 1,
+        }
+    }
+
+    low * align
+}
+
+pub fn allocator_stats() -> AllocatorStats {
+    ALLOCATOR.lock().stats()
+}
+
+/*********************************************
+* The global size class caches, if the slab
+	allocator is the global one
+*********************************************/
+#[cfg(feature = "alloc-slab")]
+pub fn size_class_caches() -> Option<[slab::SlabCacheStats; BLOCK_SIZES.len()]> {
+    Some(ALLOCATOR.lock().cache_stats())
+}
+
+#[cfg(not(feature = "alloc-slab"))]
+pub fn size_class_caches() -> Option<[slab::SlabCacheStats; BLOCK_SIZES.len()]> {
+    None
+}
+
+pub fn heap_stats() -> HeapStats {
+    HeapStats {
+        start: HEAP_START,
+        size: HEAP_MAPPED.load(Ordering::SeqCst),
+        limit: HEAP_LIMIT.load(Ordering::SeqCst),
+    }
+}
+
+/*******************************************
+* The heap never shrinks, so the limit can't
+	go below what is already mapped
+*******************************************/
+pub fn set_heap_limit(limit: usize) -> usize {
+    let limit = q_align_up(limit.max(HEAP_MAPPED.load(Ordering::SeqCst)), 4096);
+    HEAP_LIMIT.store(limit, Ordering::SeqCst);
+    limit
+}
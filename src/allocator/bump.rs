@@ -6,7 +6,7 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
-use super::{align_up, Locked};
+use super::{align_up, grow_heap, AllocatorStats, HeapAllocator, Locked};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr;
 
@@ -15,6 +15,8 @@ pub struct BumpAllocator {
     heap_end: usize,
     next: usize,
     allocations: usize,
+    total_allocations: usize,
+    bytes_in_use: usize,
 }
 
 impl BumpAllocator {
@@ -24,6 +26,8 @@ impl BumpAllocator {
             heap_end: 0,
             next: 0,
             allocations: 0,
+            total_allocations: 0,
+            bytes_in_use: 0,
         }
     }
 
@@ -34,6 +38,27 @@ impl BumpAllocator {
     }
 }
 
+impl HeapAllocator for BumpAllocator {
+    const NAME: &'static str = "bump";
+
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        Self::init(self, heap_start, heap_size);
+    }
+
+    fn stats(&mut self) -> AllocatorStats {
+        AllocatorStats {
+            name: Self::NAME,
+            allocations: self.allocations,
+            total_allocations: self.total_allocations,
+            bytes_in_use: self.bytes_in_use,
+            heap_size: self.heap_end - self.heap_start,
+            heap_free: self.heap_end - self.next,
+            largest_free_block: self.heap_end - self.next,
+            free_blocks: None,
+        }
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut bump = self.lock(); // get a mutable reference
@@ -45,18 +70,25 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
         };
 
         if alloc_end > bump.heap_end {
-            ptr::null_mut() // out of memory
-        } else {
-            bump.next = alloc_end;
-            bump.allocations += 1;
-            alloc_start as *mut u8
+            // out of memory => try to map more right after the end
+            match grow_heap(bump.heap_end, alloc_end - bump.heap_end) {
+                Ok(grown) => bump.heap_end += grown,
+                Err(_) => return ptr::null_mut(),
+            }
         }
+
+        bump.next = alloc_end;
+        bump.allocations += 1;
+        bump.total_allocations += 1;
+        bump.bytes_in_use += layout.size();
+        alloc_start as *mut u8
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: Layout) {
         let mut bump = self.lock(); // get a mutable reference
 
         bump.allocations -= 1;
+        bump.bytes_in_use -= layout.size();
         if bump.allocations == 0 {
             bump.next = bump.heap_start;
         }
@@ -0,0 +1,491 @@
+/**************************************************************************************************
+* Name : 								  allocator/debug.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 		  Heap Debugging with Redzones, Poisoning and a Side Table
+* Version : 									 0.1
+**************************************************************************************************/
+
+use crate::{lock::Mutex, memory};
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{arch::asm, fmt, ptr};
+use x86_64::VirtAddr;
+
+const REDZONE_SIZE: usize = 16;
+const SITE_DEPTH: usize = 4;
+const TABLE_SIZE: usize = 4096;
+// past this many freed entries the older ones are dropped, the newest are kept for double frees
+const MAX_FREED: usize = TABLE_SIZE / 4;
+const KEEP_FREED: usize = TABLE_SIZE / 8;
+
+const REDZONE_BYTE: u8 = 0xfd;
+const FRESH_BYTE: u8 = 0xcd;
+const POISON_BYTE: u8 = 0xdd;
+
+static TABLE: Mutex<SideTable> = Mutex::new(SideTable::new());
+static HANDLER: Mutex<ViolationHandler> = Mutex::new(default_handler);
+
+pub type ViolationHandler = fn(&Violation);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    DoubleFree,
+    LayoutMismatch,
+    RedzoneCorrupted,
+    UnknownPointer,
+    TableFull,
+}
+
+/*********************************************************
+* The return addresses of the innermost frames, starting
+	with whoever called into the allocator. Turn them into
+	lines with addr2line on the kernel binary.
+*********************************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Site(pub [usize; SITE_DEPTH]);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Violation {
+    pub kind: ViolationKind,
+    pub ptr: usize,
+    pub layout: Layout,
+    pub allocated_layout: Option<Layout>,
+    pub allocated_at: Option<Site>,
+    pub freed_at: Option<Site>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DebugStats {
+    pub tracked: usize,
+    pub freed: usize,
+    pub untracked: usize,
+    pub violations: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Empty,
+    Live,
+    Freed,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    state: State,
+    ptr: usize,
+    size: usize,
+    align: usize,
+    allocated_at: Site,
+    freed_at: Site,
+    freed_seq: usize,
+}
+
+impl Entry {
+    const EMPTY: Entry = Entry {
+        state: State::Empty,
+        ptr: 0,
+        size: 0,
+        align: 0,
+        allocated_at: Site([0; SITE_DEPTH]),
+        freed_at: Site([0; SITE_DEPTH]),
+        freed_seq: 0,
+    };
+
+    fn layout(&self) -> Layout {
+        Layout::from_size_align(self.size, self.align).unwrap()
+    }
+}
+
+/*********************************************************
+* Open addressing on the pointer, freed entries stay
+	around so a second free can be recognized until the
+	slot is needed again or they are among the oldest once
+	there are too many. It's a fixed array because it
+	can't allocate from the heap it watches.
+*********************************************************/
+struct SideTable {
+    entries: [Entry; TABLE_SIZE],
+    tracked: usize,
+    freed: usize,
+    free_count: usize,
+    untracked: usize,
+    violations: usize,
+}
+
+impl SideTable {
+    const fn new() -> Self {
+        SideTable {
+            entries: [Entry::EMPTY; TABLE_SIZE],
+            tracked: 0,
+            freed: 0,
+            free_count: 0,
+            untracked: 0,
+            violations: 0,
+        }
+    }
+
+    fn hash(ptr: usize) -> usize {
+        ((ptr >> 3).wrapping_mul(0x9e37_79b9_7f4a_7c15)) >> (usize::BITS - TABLE_SIZE.trailing_zeros())
+    }
+
+    fn find(&self, ptr: usize) -> Option<usize> {
+        let start = Self::hash(ptr);
+        for probe in 0..TABLE_SIZE {
+            let index = (start + probe) % TABLE_SIZE;
+            match self.entries[index].state {
+                State::Empty => return None,
+                _ if self.entries[index].ptr == ptr => return Some(index),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    // the entry for `ptr` if there is one, otherwise the first free or freed slot
+    fn slot_for(&self, ptr: usize) -> Option<usize> {
+        let start = Self::hash(ptr);
+        let mut reusable = None;
+        for probe in 0..TABLE_SIZE {
+            let index = (start + probe) % TABLE_SIZE;
+            match self.entries[index].state {
+                State::Empty => return Some(reusable.unwrap_or(index)),
+                _ if self.entries[index].ptr == ptr => return Some(index),
+                State::Freed if reusable.is_none() => reusable = Some(index),
+                _ => {}
+            }
+        }
+
+        reusable
+    }
+
+    fn mark_freed(&mut self, index: usize, site: Site) {
+        self.entries[index].state = State::Freed;
+        self.entries[index].freed_at = site;
+        self.entries[index].freed_seq = self.free_count;
+        self.free_count += 1;
+        self.tracked -= 1;
+        self.freed += 1;
+
+        if self.freed > MAX_FREED {
+            self.drop_old_frees();
+        }
+    }
+
+    /*****************************************************
+    * Empties all but the newest KEEP_FREED freed entries.
+    	That leaves gaps in the probe chains, so live and
+    	freed entries are moved back towards their hash
+    	until nothing sits behind an empty slot anymore.
+    *****************************************************/
+    fn drop_old_frees(&mut self) {
+        let oldest_kept = self.free_count - KEEP_FREED;
+        for entry in self.entries.iter_mut() {
+            if entry.state == State::Freed && entry.freed_seq < oldest_kept {
+                *entry = Entry::EMPTY;
+                self.freed -= 1;
+            }
+        }
+
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for index in 0..TABLE_SIZE {
+                if self.entries[index].state == State::Empty {
+                    continue;
+                }
+
+                let start = Self::hash(self.entries[index].ptr);
+                let free_slot = (0..TABLE_SIZE)
+                    .map(|probe| (start + probe) % TABLE_SIZE)
+                    .take_while(|&slot| slot != index)
+                    .find(|&slot| self.entries[slot].state == State::Empty);
+                if let Some(slot) = free_slot {
+                    self.entries[slot] = self.entries[index];
+                    self.entries[index] = Entry::EMPTY;
+                    moved = true;
+                }
+            }
+        }
+    }
+}
+
+/*********************************************************
+* Sits in front of the global allocator when the
+	heap-debug feature is on
+*********************************************************/
+pub struct DebugAllocator<A: 'static> {
+    inner: &'static A,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: &'static A) -> Self {
+        DebugAllocator { inner }
+    }
+}
+
+// the redzone in front also keeps the user pointer aligned
+fn front_size(layout: &Layout) -> usize {
+    REDZONE_SIZE.max(layout.align())
+}
+
+fn inner_layout(layout: &Layout) -> Option<Layout> {
+    let size = front_size(layout).checked_add(layout.size())?.checked_add(REDZONE_SIZE)?;
+    Layout::from_size_align(size, layout.align()).ok()
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let site = capture_site();
+        let inner = match inner_layout(&layout) {
+            Some(inner) => inner,
+            None => return ptr::null_mut(),
+        };
+
+        let raw = self.inner.alloc(inner);
+        if raw.is_null() {
+            return raw;
+        }
+
+        let front = front_size(&layout);
+        let user = raw.add(front);
+        ptr::write_bytes(raw, REDZONE_BYTE, front);
+        ptr::write_bytes(user, FRESH_BYTE, layout.size());
+        ptr::write_bytes(user.add(layout.size()), REDZONE_BYTE, REDZONE_SIZE);
+
+        let mut table = TABLE.lock();
+        match table.slot_for(user as usize) {
+            Some(index) => {
+                if table.entries[index].state == State::Freed {
+                    table.freed -= 1;
+                }
+                table.entries[index] = Entry {
+                    state: State::Live,
+                    ptr: user as usize,
+                    size: layout.size(),
+                    align: layout.align(),
+                    allocated_at: site,
+                    freed_at: Site([0; SITE_DEPTH]),
+                    freed_seq: 0,
+                };
+                table.tracked += 1;
+            }
+            None => {
+                table.untracked += 1;
+
+                // once is enough, from now on unknown pointers can't be told apart anyway
+                if table.untracked == 1 {
+                    drop(table);
+                    report(&Violation {
+                        kind: ViolationKind::TableFull,
+                        ptr: user as usize,
+                        layout,
+                        allocated_layout: None,
+                        allocated_at: None,
+                        freed_at: None,
+                    });
+                }
+            }
+        }
+
+        user
+    }
+
+    unsafe fn dealloc(&self, user: *mut u8, layout: Layout) {
+        let site = capture_site();
+
+        /*****************************************************
+        * check against the side table, the lock is dropped
+        	before anything gets reported
+        *****************************************************/
+        let (violation, actual) = {
+            let mut table = TABLE.lock();
+            match table.find(user as usize) {
+                Some(index) if table.entries[index].state == State::Freed => {
+                    let entry = table.entries[index];
+                    (Some(Violation {
+                        kind: ViolationKind::DoubleFree,
+                        ptr: user as usize,
+                        layout,
+                        allocated_layout: Some(entry.layout()),
+                        allocated_at: Some(entry.allocated_at),
+                        freed_at: Some(entry.freed_at),
+                    }), None)
+                }
+                Some(index) => {
+                    let entry = table.entries[index];
+                    table.mark_freed(index, site);
+
+                    let violation = if entry.size != layout.size() || entry.align != layout.align() {
+                        Some(Violation {
+                            kind: ViolationKind::LayoutMismatch,
+                            ptr: user as usize,
+                            layout,
+                            allocated_layout: Some(entry.layout()),
+                            allocated_at: Some(entry.allocated_at),
+                            freed_at: None,
+                        })
+                    } else {
+                        None
+                    };
+                    (violation, Some(entry))
+                }
+                // allocations made while the table was full can't be checked
+                None if table.untracked > 0 => (None, None),
+                None => (Some(Violation {
+                    kind: ViolationKind::UnknownPointer,
+                    ptr: user as usize,
+                    layout,
+                    allocated_layout: None,
+                    allocated_at: None,
+                    freed_at: None,
+                }), None),
+            }
+        };
+
+        if let Some(violation) = violation {
+            report(&violation);
+            match violation.kind {
+                // the memory isn't ours to free (again)
+                ViolationKind::DoubleFree | ViolationKind::UnknownPointer => return,
+                _ => {}
+            }
+        }
+
+        // free with the layout it was allocated with, whatever the caller said
+        let layout = actual.map(|entry| entry.layout()).unwrap_or(layout);
+        let front = front_size(&layout);
+        let raw = user.sub(front);
+
+        let front_intact = core::slice::from_raw_parts(raw, front).iter().all(|&byte| byte == REDZONE_BYTE);
+        let back_intact = core::slice::from_raw_parts(user.add(layout.size()), REDZONE_SIZE)
+            .iter()
+            .all(|&byte| byte == REDZONE_BYTE);
+        if !front_intact || !back_intact {
+            report(&Violation {
+                kind: ViolationKind::RedzoneCorrupted,
+                ptr: user as usize,
+                layout,
+                allocated_layout: actual.map(|entry| entry.layout()),
+                allocated_at: actual.map(|entry| entry.allocated_at),
+                freed_at: None,
+            });
+        }
+
+        ptr::write_bytes(user, POISON_BYTE, layout.size());
+        self.inner.dealloc(raw, inner_layout(&layout).unwrap());
+    }
+}
+
+fn report(violation: &Violation) {
+    TABLE.lock().violations += 1;
+    let handler = *HANDLER.lock();
+    handler(violation);
+}
+
+fn default_handler(violation: &Violation) {
+    panic!("{}", violation);
+}
+
+/*********************************************************
+* Replaces what happens on a violation, panicking is the
+	default. Returns the previous handler.
+*********************************************************/
+pub fn set_violation_handler(handler: ViolationHandler) -> ViolationHandler {
+    core::mem::replace(&mut *HANDLER.lock(), handler)
+}
+
+pub fn stats() -> DebugStats {
+    let table = TABLE.lock();
+    DebugStats {
+        tracked: table.tracked,
+        freed: table.freed,
+        untracked: table.untracked,
+        violations: table.violations,
+    }
+}
+
+/*********************************************************
+* Walks the frame pointer chain. Frame pointers are only
+	kept when built with -C force-frame-pointers=yes, so
+	rbp can hold anything: a frame has to be further up
+	the current stack than the last one and mapped before
+	it's read, otherwise the site just ends early.
+*********************************************************/
+#[inline(never)]
+fn capture_site() -> Site {
+    let mut site = Site([0; SITE_DEPTH]);
+    let (mut rbp, rsp): (usize, usize);
+    unsafe {
+        asm!("mov {}, rbp", "mov {}, rsp", out(reg) rbp, out(reg) rsp, options(nomem, nostack, preserves_flags));
+    }
+
+    // the first two return addresses go back into the allocator and its shim, those are skipped
+    let stack_limit = rsp.saturating_add(256 * 1024);
+    let mut lowest = rsp;
+    for depth in 0..SITE_DEPTH + 2 {
+        if rbp < lowest || rbp % 8 != 0 || rbp >= stack_limit || !is_mapped(rbp) || !is_mapped(rbp + 8) {
+            break;
+        }
+
+        let (next, return_address) = unsafe { (*(rbp as *const usize), *((rbp + 8) as *const usize)) };
+        if depth >= 2 {
+            site.0[depth - 2] = return_address;
+        }
+        lowest = rbp + 16;
+        rbp = next;
+    }
+
+    site
+}
+
+// without taking any locks, the allocator may be called with the page tables locked
+fn is_mapped(addr: usize) -> bool {
+    VirtAddr::try_new(addr as u64).map_or(false, |addr| memory::walk_page_tables(addr).phys.is_some())
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for &address in self.0.iter().take_while(|&&address| address != 0) {
+            if !first {
+                write!(f, " <- ")?;
+            }
+            write!(f, "{:#x}", address)?;
+            first = false;
+        }
+
+        if first {
+            write!(f, "unknown")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ViolationKind::DoubleFree => write!(f, "double free"),
+            ViolationKind::LayoutMismatch => write!(f, "free with a different layout"),
+            ViolationKind::RedzoneCorrupted => write!(f, "write outside of an allocation"),
+            ViolationKind::UnknownPointer => write!(f, "free of a pointer that was never allocated"),
+            ViolationKind::TableFull => write!(f, "allocation past a full side table"),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "heap {} at {:#x} ({} bytes, align {})", self.kind, self.ptr, self.layout.size(), self.layout.align())?;
+        if let Some(layout) = self.allocated_layout {
+            write!(f, ", allocated as {} bytes, align {}", layout.size(), layout.align())?;
+        }
+        if let Some(site) = self.allocated_at {
+            write!(f, "\n  allocated at {}", site)?;
+        }
+        if let Some(site) = self.freed_at {
+            write!(f, "\n  freed at {}", site)?;
+        }
+        Ok(())
+    }
+}
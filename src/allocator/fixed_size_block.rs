@@ -6,7 +6,7 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
-use super::Locked;
+use super::{grow_heap, largest_free_block, AllocatorStats, HeapAllocator, Locked};
 use alloc::alloc::{Layout, GlobalAlloc};
 use core::{mem, ptr, ptr::NonNull};
 
@@ -14,17 +14,20 @@ struct ListNode {
     next: Option<&'static mut ListNode>,
 }
 
-const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
     fallback_allocator: linked_list_allocator::Heap,
+    allocations: usize,
+    total_allocations: usize,
+    bytes_in_use: usize,
 }
 
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
-        match list_index(&layout) {
+        let ptr = match list_index(&layout) {
             Some(index) => {
                 match allocator.list_heads[index].take() {
                     Some(node) => {
@@ -47,10 +50,19 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 }
             }
             None => allocator.fallback_alloc(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.allocations += 1;
+            allocator.total_allocations += 1;
+            allocator.bytes_in_use += layout.size();
         }
+        ptr
     }
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        allocator.allocations -= 1;
+        allocator.bytes_in_use -= layout.size();
         match list_index(&layout) {
             Some(index) => {
                 let new_node = ListNode {
@@ -73,8 +85,23 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
 
 impl FixedSizeBlockAllocator {
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        /******************************************************
+        * out of heap => map more pages and try again, leaving
+        	room for alignment and the allocator's bookkeeping
+        ******************************************************/
+        let min_size = layout.size() + layout.align() + 2 * mem::size_of::<usize>();
+        match grow_heap(self.fallback_allocator.top() as usize, min_size) {
+            Ok(grown) => {
+                unsafe { self.fallback_allocator.extend(grown) };
+                match self.fallback_allocator.allocate_first_fit(layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => ptr::null_mut(),
+                }
+            }
             Err(_) => ptr::null_mut(),
         }
     }
@@ -88,6 +115,39 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            allocations: 0,
+            total_allocations: 0,
+            bytes_in_use: 0,
+        }
+    }
+}
+
+impl HeapAllocator for FixedSizeBlockAllocator {
+    const NAME: &'static str = "fixed size block";
+
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        Self::init(self, heap_start, heap_size);
+    }
+
+    fn stats(&mut self) -> AllocatorStats {
+        let mut free_blocks = [0; BLOCK_SIZES.len()];
+        for (index, head) in self.list_heads.iter().enumerate() {
+            let mut node = head.as_deref();
+            while let Some(current) = node {
+                free_blocks[index] += 1;
+                node = current.next.as_deref();
+            }
+        }
+
+        AllocatorStats {
+            name: Self::NAME,
+            allocations: self.allocations,
+            total_allocations: self.total_allocations,
+            bytes_in_use: self.bytes_in_use,
+            heap_size: self.fallback_allocator.size(),
+            heap_free: self.fallback_allocator.free(),
+            largest_free_block: largest_free_block(&mut self.fallback_allocator),
+            free_blocks: Some(free_blocks),
         }
     }
 }
@@ -6,7 +6,7 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
-use super::{align_up, Locked};
+use super::{align_up, grow_heap, AllocatorStats, HeapAllocator, Locked};
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{mem, ptr::{self}};
 
@@ -15,54 +15,174 @@ struct ListNode {
     next: Option<&'static mut ListNode>,
 }
 
+/*********************************************************
+* FirstFit takes the lowest region that fits, BestFit
+	takes the smallest one which leaves bigger regions
+	alone at the cost of walking the whole list
+*********************************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStrategy {
+    FirstFit,
+    BestFit,
+}
+
+/*********************************************************
+* The free list is kept sorted by address so neighbouring
+	regions can be merged when memory is freed
+*********************************************************/
 pub struct LinkedListAllocator {
     head: ListNode,
+    strategy: FitStrategy,
+    heap_start: usize,
+    heap_end: usize,
+    allocations: usize,
+    total_allocations: usize,
+    bytes_in_use: usize,
 }
 
 impl LinkedListAllocator {
     pub const fn new() -> Self {
+        Self::with_strategy(FitStrategy::FirstFit)
+    }
+
+    pub const fn with_strategy(strategy: FitStrategy) -> Self {
         Self {
             head: ListNode::new(0),
+            strategy,
+            heap_start: 0,
+            heap_end: 0,
+            allocations: 0,
+            total_allocations: 0,
+            bytes_in_use: 0,
         }
     }
 
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
         self.add_free_region(heap_start, heap_size);
     }
 
+    pub fn strategy(&self) -> FitStrategy {
+        self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: FitStrategy) {
+        self.strategy = strategy;
+    }
+
+    pub fn free_regions(&self) -> usize {
+        self.regions().count()
+    }
+
+    pub fn free_size(&self) -> usize {
+        self.regions().map(|region| region.size).sum()
+    }
+
+    pub fn largest_free_region(&self) -> usize {
+        self.regions().map(|region| region.size).max().unwrap_or(0)
+    }
+
+    fn regions(&self) -> impl Iterator<Item = &ListNode> {
+        let mut current = self.head.next.as_deref();
+        core::iter::from_fn(move || {
+            let region = current?;
+            current = region.next.as_deref();
+            Some(region)
+        })
+    }
+
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
         assert!(size >= mem::size_of::<ListNode>());
 
+        // find the last region that starts below addr
+        let mut current = &mut self.head;
+        while current.next.as_ref().map_or(false, |next| next.start_addr() < addr) {
+            current = current.next.as_mut().unwrap();
+        }
+
+        let next = current.next.take();
+        assert!(current.size == 0 || current.end_addr() <= addr, "freed region overlaps a free region");
+        assert!(next.as_ref().map_or(true, |next| addr + size <= next.start_addr()), "freed region overlaps a free region");
+
+        // merge with the following region
+        let (size, next) = match next {
+            Some(next) if addr + size == next.start_addr() => (size + next.size, next.next.take()),
+            next => (size, next),
+        };
+
+        // merge with the preceding region, the dummy head has size 0 and is never merged
+        if current.size > 0 && current.end_addr() == addr {
+            current.size += size;
+            current.next = next;
+            return;
+        }
+
         let mut node = ListNode::new(size);
-        node.next = self.head.next.take();
+        node.next = next;
         let node_ptr = addr as *mut ListNode;
         node_ptr.write(node);
-        self.head.next = Some(&mut *node_ptr)
+        current.next = Some(&mut *node_ptr)
     }
 
     fn find_region(&mut self, size: usize, align: usize)
         -> Option<(&'static mut ListNode, usize)>
     {
+        let target = match self.strategy {
+            FitStrategy::FirstFit => None,
+            FitStrategy::BestFit => Some(self.best_fit(size, align)?),
+        };
+
         let mut current = &mut self.head;
         while let Some(ref mut region) = current.next {
-            if let Ok(alloc_start) = Self::alloc_from_region(&region, size, align) {
-                let next = region.next.take();
-                let ret = Some((current.next.take().unwrap(), alloc_start));
-                current.next = next;
-                return ret;
-            } else {
-                current = current.next.as_mut().unwrap();
+            match Self::alloc_from_region(&region, size, align) {
+                Ok(alloc_start) if target.map_or(true, |start| start == region.start_addr()) => {
+                    let next = region.next.take();
+                    let ret = Some((current.next.take().unwrap(), alloc_start));
+                    current.next = next;
+                    return ret;
+                }
+                _ => current = current.next.as_mut().unwrap(),
             }
         }
 
         None
     }
 
+    /*********************************************
+    * Returns the start of the smallest region the
+    	allocation fits in
+    *********************************************/
+    fn best_fit(&self, size: usize, align: usize) -> Option<usize> {
+        let mut best: Option<&ListNode> = None;
+
+        for region in self.regions() {
+            if Self::alloc_from_region(region, size, align).is_err() {
+                continue;
+            }
+            if best.map_or(true, |best| region.size < best.size) {
+                best = Some(region);
+            }
+            if region.size == size {
+                break;
+            }
+        }
+
+        best.map(|region| region.start_addr())
+    }
+
     fn alloc_from_region(region: &ListNode, size: usize, align: usize)
         -> Result<usize, ()>
     {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+
+        // padding in front of the allocation goes back on the free list, so it has to fit a node
+        let padding = alloc_start - region.start_addr();
+        if padding > 0 && padding < mem::size_of::<ListNode>() {
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
+
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr() {
@@ -87,6 +207,27 @@ impl LinkedListAllocator {
     }
 }
 
+impl HeapAllocator for LinkedListAllocator {
+    const NAME: &'static str = "linked list";
+
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        Self::init(self, heap_start, heap_size);
+    }
+
+    fn stats(&mut self) -> AllocatorStats {
+        AllocatorStats {
+            name: Self::NAME,
+            allocations: self.allocations,
+            total_allocations: self.total_allocations,
+            bytes_in_use: self.bytes_in_use,
+            heap_size: self.heap_end - self.heap_start,
+            heap_free: self.free_size(),
+            largest_free_block: self.largest_free_region(),
+            free_blocks: None,
+        }
+    }
+}
+
 impl ListNode {
     const fn new(size: usize) -> Self {
         Self {
@@ -109,12 +250,35 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         let (size, align) = LinkedListAllocator::size_align(layout);
         let mut allocator = self.lock();
 
-        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
+        let mut found = allocator.find_region(size, align);
+        if found.is_none() {
+            // out of memory => map more right after the end, it merges with a free region there
+            let min_size = size + align + mem::size_of::<ListNode>();
+            if let Ok(grown) = grow_heap(allocator.heap_end, min_size) {
+                let heap_end = allocator.heap_end;
+                allocator.add_free_region(heap_end, grown);
+                allocator.heap_end += grown;
+                found = allocator.find_region(size, align);
+            }
+        }
+
+        if let Some((region, alloc_start)) = found {
+            let region_start = region.start_addr();
+            let region_end = region.end_addr();
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
-            let excess_size = region.end_addr() - alloc_end;
+
+            let padding = alloc_start - region_start;
+            if padding > 0 {
+                allocator.add_free_region(region_start, padding);
+            }
+            let excess_size = region_end - alloc_end;
             if excess_size > 0 {
                 allocator.add_free_region(alloc_end, excess_size);
             }
+
+            allocator.allocations += 1;
+            allocator.total_allocations += 1;
+            allocator.bytes_in_use += layout.size();
             alloc_start as *mut u8
         } else {
             ptr::null_mut()
@@ -124,6 +288,10 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let (size, _) = LinkedListAllocator::size_align(layout);
 
-        self.lock().add_free_region(ptr as usize, size)
+        let mut allocator = self.lock();
+
+        allocator.allocations -= 1;
+        allocator.bytes_in_use -= layout.size();
+        allocator.add_free_region(ptr as usize, size)
     }
-}
\ No newline at end of file
+}
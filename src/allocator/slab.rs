@@ -0,0 +1,521 @@
+/**************************************************************************************************
+* Name : 								  allocator/slab.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			  Slab Allocation with Caches Backed by Page Frames
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{grow_heap, largest_free_block, AllocatorStats, HeapAllocator, Locked, fixed_size_block::BLOCK_SIZES};
+use crate::{lock::Mutex, memory};
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::{
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use x86_64::{structures::paging::PhysFrame, PhysAddr, VirtAddr};
+
+const PAGE_SIZE: usize = 4096;
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+const MAX_EMPTY_SLABS: usize = 1; // kept per cache so a cache hovering at a slab boundary doesn't thrash
+const MAX_OBJECT_CACHES: usize = 16;
+
+const CLASS_NAMES: [&str; BLOCK_SIZES.len()] = [
+    "size-8", "size-16", "size-32", "size-64", "size-128", "size-256", "size-512", "size-1024", "size-2048",
+];
+
+static OBJECT_CACHES: Mutex<[Option<&'static Locked<SlabCache>>; MAX_OBJECT_CACHES]> =
+    Mutex::new([None; MAX_OBJECT_CACHES]);
+
+struct FreeObject {
+    next: Option<&'static mut FreeObject>,
+}
+
+/*********************************************************
+* Sits at the start of every slab, the objects follow it.
+	Slabs are aligned to a power of two so the header of
+	an object is found by masking its address.
+*********************************************************/
+struct SlabHeader {
+    next: Option<&'static mut SlabHeader>,
+    free: Option<&'static mut FreeObject>,
+    in_use: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabCacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub pages_per_slab: usize,
+    pub slabs: usize,
+    pub full_slabs: usize,
+    pub empty_slabs: usize,
+    pub objects_in_use: usize,
+}
+
+/*********************************************************
+* Objects of one size, in slabs of whole page frames that
+	are reached through the physical memory mapping.
+	Partially used and empty slabs are on `partial`, full
+	ones on `full`.
+*********************************************************/
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    object_align: usize,
+    slab_pages: usize,
+    slab_align_pages: usize,
+    partial: Option<&'static mut SlabHeader>,
+    full: Option<&'static mut SlabHeader>,
+    slabs: usize,
+    empty_slabs: usize,
+    objects_in_use: usize,
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, size: usize, align: usize) -> Self {
+        // every free object has to hold a FreeObject
+        let object_align = if align > mem::align_of::<FreeObject>() { align } else { mem::align_of::<FreeObject>() };
+        let size = if size > mem::size_of::<FreeObject>() { size } else { mem::size_of::<FreeObject>() };
+        let object_size = (size + object_align - 1) / object_align * object_align;
+
+        let first_object = (mem::size_of::<SlabHeader>() + object_align - 1) / object_align * object_align;
+        let slab_pages = (first_object + MIN_OBJECTS_PER_SLAB * object_size + PAGE_SIZE - 1) / PAGE_SIZE;
+
+        SlabCache {
+            name,
+            object_size,
+            object_align,
+            slab_pages,
+            slab_align_pages: slab_pages.next_power_of_two(),
+            partial: None,
+            full: None,
+            slabs: 0,
+            empty_slabs: 0,
+            objects_in_use: 0,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn first_object(&self) -> usize {
+        (mem::size_of::<SlabHeader>() + self.object_align - 1) / self.object_align * self.object_align
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (self.slab_pages * PAGE_SIZE - self.first_object()) / self.object_size
+    }
+
+    pub fn alloc(&mut self) -> *mut u8 {
+        if self.partial.is_none() && !self.grow() {
+            return ptr::null_mut();
+        }
+
+        let slab = self.partial.as_mut().unwrap();
+        if slab.in_use == 0 {
+            self.empty_slabs -= 1;
+        }
+
+        let object = slab.free.take().expect("slab on the partial list is full");
+        slab.free = object.next.take();
+        slab.in_use += 1;
+
+        // the slab just ran out => move it over to the full list
+        if slab.free.is_none() {
+            let slab = self.partial.take().unwrap();
+            self.partial = slab.next.take();
+            slab.next = self.full.take();
+            self.full = Some(slab);
+        }
+
+        self.objects_in_use += 1;
+        object as *mut FreeObject as *mut u8
+    }
+
+    pub unsafe fn free(&mut self, ptr: *mut u8) {
+        let slab_bytes = self.slab_align_pages * PAGE_SIZE;
+        let slab_ptr = (ptr as usize & !(slab_bytes - 1)) as *mut SlabHeader;
+
+        let offset = ptr as usize - slab_ptr as usize;
+        assert!(
+            offset >= self.first_object()
+                && offset < self.first_object() + self.objects_per_slab() * self.object_size
+                && (offset - self.first_object()) % self.object_size == 0,
+            "{:p} is not an object of slab cache {}", ptr, self.name
+        );
+
+        let was_full = (*slab_ptr).free.is_none();
+
+        let object_ptr = ptr as *mut FreeObject;
+        object_ptr.write(FreeObject { next: (*slab_ptr).free.take() });
+        (*slab_ptr).free = Some(&mut *object_ptr);
+        (*slab_ptr).in_use -= 1;
+        self.objects_in_use -= 1;
+
+        if was_full {
+            let slab = unlink(&mut self.full, slab_ptr).expect("full slab missing from its list");
+            slab.next = self.partial.take();
+            self.partial = Some(slab);
+        }
+
+        if (*slab_ptr).in_use == 0 {
+            if self.empty_slabs >= MAX_EMPTY_SLABS {
+                let slab = unlink(&mut self.partial, slab_ptr).expect("empty slab missing from its list");
+                self.release(slab);
+            } else {
+                self.empty_slabs += 1;
+            }
+        }
+    }
+
+    /*********************************************
+    * Gives every empty slab back to the frame
+    	allocator, returns how many were freed
+    *********************************************/
+    pub fn shrink(&mut self) -> usize {
+        let mut released = 0;
+        while let Some(slab_ptr) = self.find_empty_slab() {
+            let slab = unlink(&mut self.partial, slab_ptr).unwrap();
+            self.release(slab);
+            self.empty_slabs -= 1;
+            released += 1;
+        }
+
+        released
+    }
+
+    fn find_empty_slab(&mut self) -> Option<*mut SlabHeader> {
+        let mut slab = self.partial.as_deref_mut();
+        while let Some(current) = slab {
+            if current.in_use == 0 {
+                return Some(current as *mut SlabHeader);
+            }
+            slab = current.next.as_deref_mut();
+        }
+
+        None
+    }
+
+    fn grow(&mut self) -> bool {
+        let frames = match memory::allocate_contiguous_frames(self.slab_pages, self.slab_align_pages, None) {
+            Some(frames) => frames,
+            None => return false,
+        };
+
+        let start = memory::physical_memory_offset() + frames.start.start_address().as_u64();
+        let slab_ptr: *mut SlabHeader = start.as_mut_ptr();
+        assert_eq!(start.as_u64() as usize % (self.slab_align_pages * PAGE_SIZE), 0);
+
+        /*********************************************
+        * chain the objects back to front, so the
+        	first one handed out is at the lowest address
+        *********************************************/
+        let mut free = None;
+        for index in (0..self.objects_per_slab()).rev() {
+            let object_ptr = (start.as_u64() as usize + self.first_object() + index * self.object_size) as *mut FreeObject;
+            unsafe {
+                object_ptr.write(FreeObject { next: free });
+                free = Some(&mut *object_ptr);
+            }
+        }
+
+        unsafe {
+            slab_ptr.write(SlabHeader {
+                next: self.partial.take(),
+                free,
+                in_use: 0,
+            });
+            self.partial = Some(&mut *slab_ptr);
+        }
+
+        self.slabs += 1;
+        self.empty_slabs += 1;
+        true
+    }
+
+    fn release(&mut self, slab: &'static mut SlabHeader) {
+        let start = VirtAddr::new(slab as *mut SlabHeader as u64);
+        let phys = PhysAddr::new(start.as_u64() - memory::physical_memory_offset().as_u64());
+        let first = PhysFrame::containing_address(phys);
+
+        unsafe {
+            memory::deallocate_contiguous_frames(PhysFrame::range(first, first + self.slab_pages as u64));
+        }
+        self.slabs -= 1;
+    }
+
+    pub fn stats(&self) -> SlabCacheStats {
+        let mut full_slabs = 0;
+        let mut slab = self.full.as_deref();
+        while let Some(current) = slab {
+            full_slabs += 1;
+            slab = current.next.as_deref();
+        }
+
+        SlabCacheStats {
+            name: self.name,
+            object_size: self.object_size,
+            objects_per_slab: self.objects_per_slab(),
+            pages_per_slab: self.slab_pages,
+            slabs: self.slabs,
+            full_slabs,
+            empty_slabs: self.empty_slabs,
+            objects_in_use: self.objects_in_use,
+        }
+    }
+}
+
+fn unlink(list: &mut Option<&'static mut SlabHeader>, slab: *mut SlabHeader) -> Option<&'static mut SlabHeader> {
+    let mut current = list;
+    while current.as_deref().map_or(false, |node| !ptr::eq(node, slab)) {
+        current = &mut current.as_mut().unwrap().next;
+    }
+
+    let node = current.take()?;
+    *current = node.next.take();
+    Some(node)
+}
+
+/*********************************************************
+* A named cache for one kernel structure, like tasks or
+	page tables. It shows up in `slabinfo` after its
+	first allocation.
+*********************************************************/
+pub struct ObjectCache<T> {
+    cache: Locked<SlabCache>,
+    registered: AtomicBool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ObjectCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        ObjectCache {
+            cache: Locked::new(SlabCache::new(name, mem::size_of::<T>(), mem::align_of::<T>())),
+            registered: AtomicBool::new(false),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn alloc(&'static self, value: T) -> Option<CacheBox<T>> {
+        if !self.registered.swap(true, Ordering::SeqCst) {
+            register(&self.cache);
+        }
+
+        let ptr = NonNull::new(self.cache.lock().alloc() as *mut T)?;
+        unsafe { ptr.as_ptr().write(value) };
+
+        Some(CacheBox { ptr, cache: self })
+    }
+
+    // `ptr` has to come from `CacheBox::into_raw` on a box of this cache
+    pub unsafe fn from_raw(&'static self, ptr: *mut T) -> CacheBox<T> {
+        CacheBox { ptr: NonNull::new_unchecked(ptr), cache: self }
+    }
+
+    pub fn shrink(&self) -> usize {
+        self.cache.lock().shrink()
+    }
+
+    pub fn stats(&self) -> SlabCacheStats {
+        self.cache.lock().stats()
+    }
+}
+
+fn register(cache: &'static Locked<SlabCache>) {
+    let mut caches = OBJECT_CACHES.lock();
+    // it's only for listing, a cache that doesn't fit still works
+    if let Some(slot) = caches.iter_mut().find(|slot| slot.is_none()) {
+        *slot = Some(cache);
+    }
+}
+
+/*********************************************
+* Stats of all named caches, for `slabinfo`
+*********************************************/
+pub fn object_cache_stats() -> alloc::vec::Vec<SlabCacheStats> {
+    let caches = *OBJECT_CACHES.lock();
+    caches.iter().flatten().map(|cache| cache.lock().stats()).collect()
+}
+
+/*********************************************************
+* Owns an object from an `ObjectCache` and gives it back
+	when dropped
+*********************************************************/
+pub struct CacheBox<T: 'static> {
+    ptr: NonNull<T>,
+    cache: &'static ObjectCache<T>,
+}
+
+impl<T> CacheBox<T> {
+    pub fn as_ptr(this: &Self) -> *mut T {
+        this.ptr.as_ptr()
+    }
+
+    // the object stays allocated until `ObjectCache::from_raw` takes it back
+    pub fn into_raw(this: Self) -> *mut T {
+        let ptr = this.ptr.as_ptr();
+        mem::forget(this);
+        ptr
+    }
+
+    // slabs live in the physical memory mapping, so this is a plain subtraction
+    pub fn phys_addr(this: &Self) -> PhysAddr {
+        PhysAddr::new(this.ptr.as_ptr() as u64 - memory::physical_memory_offset().as_u64())
+    }
+}
+
+impl<T> Deref for CacheBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for CacheBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for CacheBox<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.cache.cache.lock().free(self.ptr.as_ptr() as *mut u8);
+        }
+    }
+}
+
+unsafe impl<T: Send> Send for CacheBox<T> {}
+unsafe impl<T: Sync> Sync for CacheBox<T> {}
+
+/*********************************************************
+* Global allocator with a slab cache per block size,
+	bigger allocations go to a linked list fallback heap
+*********************************************************/
+pub struct SlabAllocator {
+    caches: [SlabCache; BLOCK_SIZES.len()],
+    fallback_allocator: linked_list_allocator::Heap,
+    allocations: usize,
+    total_allocations: usize,
+    bytes_in_use: usize,
+}
+
+impl SlabAllocator {
+    pub const fn new() -> Self {
+        SlabAllocator {
+            caches: [
+                Self::class(0), Self::class(1), Self::class(2),
+                Self::class(3), Self::class(4), Self::class(5),
+                Self::class(6), Self::class(7), Self::class(8),
+            ],
+            fallback_allocator: linked_list_allocator::Heap::empty(),
+            allocations: 0,
+            total_allocations: 0,
+            bytes_in_use: 0,
+        }
+    }
+
+    const fn class(index: usize) -> SlabCache {
+        SlabCache::new(CLASS_NAMES[index], BLOCK_SIZES[index], BLOCK_SIZES[index])
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.init(heap_start as *mut u8, heap_size);
+    }
+
+    pub fn cache_stats(&self) -> [SlabCacheStats; BLOCK_SIZES.len()] {
+        let mut stats = [self.caches[0].stats(); BLOCK_SIZES.len()];
+        for (stat, cache) in stats.iter_mut().zip(self.caches.iter()) {
+            *stat = cache.stats();
+        }
+
+        stats
+    }
+
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        let min_size = layout.size() + layout.align() + 2 * mem::size_of::<usize>();
+        match grow_heap(self.fallback_allocator.top() as usize, min_size) {
+            Ok(grown) => {
+                unsafe { self.fallback_allocator.extend(grown) };
+                match self.fallback_allocator.allocate_first_fit(layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => ptr::null_mut(),
+                }
+            }
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+impl HeapAllocator for SlabAllocator {
+    const NAME: &'static str = "slab";
+
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        Self::init(self, heap_start, heap_size);
+    }
+
+    fn stats(&mut self) -> AllocatorStats {
+        let mut free_blocks = [0; BLOCK_SIZES.len()];
+        for (free, cache) in free_blocks.iter_mut().zip(self.caches.iter()) {
+            *free = cache.slabs * cache.objects_per_slab() - cache.objects_in_use;
+        }
+
+        AllocatorStats {
+            name: Self::NAME,
+            allocations: self.allocations,
+            total_allocations: self.total_allocations,
+            bytes_in_use: self.bytes_in_use,
+            heap_size: self.fallback_allocator.size(),
+            heap_free: self.fallback_allocator.free(),
+            largest_free_block: largest_free_block(&mut self.fallback_allocator),
+            free_blocks: Some(free_blocks),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<SlabAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut allocator = self.lock();
+        let ptr = match list_index(&layout) {
+            Some(index) => allocator.caches[index].alloc(),
+            None => allocator.fallback_alloc(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.allocations += 1;
+            allocator.total_allocations += 1;
+            allocator.bytes_in_use += layout.size();
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut allocator = self.lock();
+        allocator.allocations -= 1;
+        allocator.bytes_in_use -= layout.size();
+        match list_index(&layout) {
+            Some(index) => allocator.caches[index].free(ptr),
+            None => {
+                let ptr = NonNull::new(ptr).unwrap();
+                allocator.fallback_allocator.deallocate(ptr, layout);
+            }
+        }
+    }
+}
+
+fn list_index(layout: &Layout) -> Option<usize> {
+    let required_block_size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
+}
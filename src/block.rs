@@ -0,0 +1,67 @@
+/**************************************************************************************************
+* Name : 									  block.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					 Devices Read and Written in Sectors
+* Version : 									 0.1
+**************************************************************************************************/
+
+pub mod ata;
+
+use core::fmt;
+
+pub const SECTOR_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    // nothing answered, or it isn't a disk
+    NoDevice,
+    // past the end of the device, or not whole sectors
+    OutOfRange,
+    // the error register after the failed command
+    DeviceError(u8),
+    Timeout,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::NoDevice => write!(f, "no device"),
+            BlockError::OutOfRange => write!(f, "outside of the device"),
+            BlockError::DeviceError(error) => write!(f, "device error {:#04x}", error),
+            BlockError::Timeout => write!(f, "device timed out"),
+        }
+    }
+}
+
+/*********************************************************
+* Something that stores sectors of SECTOR_SIZE bytes.
+	Buffers are always a whole number of sectors long.
+*********************************************************/
+pub trait BlockDevice: Send {
+    fn name(&self) -> &str;
+
+    fn sector_count(&self) -> u64;
+
+    fn read(&mut self, sector: u64, buffer: &mut [u8]) -> Result<(), BlockError>;
+
+    fn write(&mut self, sector: u64, data: &[u8]) -> Result<(), BlockError>;
+
+    // makes sure everything written so far is stored
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
+    }
+}
+
+// the number of sectors in `len` bytes at `sector`, if they fit on a device of `count` sectors
+pub fn check_range(sector: u64, len: usize, count: u64) -> Result<u64, BlockError> {
+    if len % SECTOR_SIZE != 0 {
+        return Err(BlockError::OutOfRange);
+    }
+
+    let sectors = (len / SECTOR_SIZE) as u64;
+    match sector.checked_add(sectors) {
+        Some(end) if end <= count => Ok(sectors),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
@@ -0,0 +1,288 @@
+/**************************************************************************************************
+* Name : 								    block/ata.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					    ATA Disks over Programmed I/O
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{check_range, BlockDevice, BlockError, SECTOR_SIZE};
+use crate::lock::Mutex;
+use alloc::{format, string::String};
+use x86_64::instructions::port::Port;
+
+const PRIMARY_IO: u16 = 0x1f0;
+const PRIMARY_CONTROL: u16 = 0x3f6;
+const SECONDARY_IO: u16 = 0x170;
+const SECONDARY_CONTROL: u16 = 0x376;
+
+// registers, as offsets from the I/O base
+const DATA: u16 = 0;
+const ERROR: u16 = 1;
+const SECTOR_COUNT: u16 = 2;
+const LBA_LOW: u16 = 3;
+const LBA_MID: u16 = 4;
+const LBA_HIGH: u16 = 5;
+const DRIVE_HEAD: u16 = 6;
+const STATUS_COMMAND: u16 = 7;
+
+const STATUS_ERROR: u8 = 1 << 0;
+const STATUS_DATA_REQUEST: u8 = 1 << 3;
+const STATUS_DEVICE_FAULT: u8 = 1 << 5;
+const STATUS_BUSY: u8 = 1 << 7;
+
+const COMMAND_READ_SECTORS: u8 = 0x20;
+const COMMAND_WRITE_SECTORS: u8 = 0x30;
+const COMMAND_CACHE_FLUSH: u8 = 0xe7;
+const COMMAND_IDENTIFY: u8 = 0xec;
+
+// set in the device control register, the drive never raises IRQ 14 or 15
+const CONTROL_NO_INTERRUPTS: u8 = 1 << 1;
+
+// 28 bit addresses, a command moves at most 256 sectors
+const MAX_SECTORS: u64 = 1 << 28;
+const SECTORS_PER_COMMAND: u64 = 256;
+
+// status reads before giving up on the drive
+const TIMEOUT: usize = 1_000_000;
+
+/*********************************************************
+* Both drives on a bus share its registers, a command
+	has to finish before the other drive gets the bus
+*********************************************************/
+static BUSES: [Mutex<()>; 2] = [Mutex::new(()), Mutex::new(())];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+    Primary,
+    Secondary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Drive {
+    Master,
+    Slave,
+}
+
+/*********************************************************
+* A disk on one of the two legacy IDE buses. Everything
+	is polled, so it works with interrupts disabled, in
+	the page fault handler too.
+*********************************************************/
+pub struct AtaDisk {
+    bus: Bus,
+    drive: Drive,
+    name: String,
+    model: String,
+    sectors: u64,
+}
+
+impl AtaDisk {
+    /*****************************************************
+    * Asks the drive to identify itself. Fails for empty
+    	slots and for drives that aren't ATA disks, like
+    	CD drives.
+    *****************************************************/
+    pub fn open(bus: Bus, drive: Drive) -> Result<AtaDisk, BlockError> {
+        let mut disk = AtaDisk {
+            bus,
+            drive,
+            name: format!("ATA {:?} {:?}", bus, drive).to_lowercase(),
+            model: String::new(),
+            sectors: 0,
+        };
+
+        let identity = disk.identify()?;
+        disk.sectors = (identity[60] as u64 | (identity[61] as u64) << 16).min(MAX_SECTORS);
+        if disk.sectors == 0 {
+            // no LBA, cylinders and heads aren't worth it
+            return Err(BlockError::NoDevice);
+        }
+
+        // two characters per word, the first one in the high byte
+        let model: String = identity[27..47]
+            .iter()
+            .flat_map(|word| [(word >> 8) as u8 as char, *word as u8 as char])
+            .collect();
+        disk.model = String::from(model.trim());
+
+        Ok(disk)
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn identify(&mut self) -> Result<[u16; 256], BlockError> {
+        let _bus = BUSES[self.bus as usize].lock();
+        unsafe {
+            self.select(0);
+            self.port(SECTOR_COUNT).write(0);
+            self.port(LBA_LOW).write(0);
+            self.port(LBA_MID).write(0);
+            self.port(LBA_HIGH).write(0);
+            self.port(STATUS_COMMAND).write(COMMAND_IDENTIFY);
+            self.delay();
+
+            // nothing there, or a bus without drives floating high
+            let status = self.port(STATUS_COMMAND).read();
+            if status == 0 || status == 0xff {
+                return Err(BlockError::NoDevice);
+            }
+            self.wait_ready()?;
+
+            // ATAPI and SATA drives put their signature here instead
+            if self.port(LBA_MID).read() != 0 || self.port(LBA_HIGH).read() != 0 {
+                return Err(BlockError::NoDevice);
+            }
+            self.wait_data().map_err(|_| BlockError::NoDevice)?;
+
+            let mut identity = [0u16; 256];
+            let mut data = Port::<u16>::new(self.io_base() + DATA);
+            for word in identity.iter_mut() {
+                *word = data.read();
+            }
+            Ok(identity)
+        }
+    }
+
+    fn io_base(&self) -> u16 {
+        match self.bus {
+            Bus::Primary => PRIMARY_IO,
+            Bus::Secondary => SECONDARY_IO,
+        }
+    }
+
+    fn control(&self) -> Port<u8> {
+        match self.bus {
+            Bus::Primary => Port::new(PRIMARY_CONTROL),
+            Bus::Secondary => Port::new(SECONDARY_CONTROL),
+        }
+    }
+
+    fn port(&self, register: u16) -> Port<u8> {
+        Port::new(self.io_base() + register)
+    }
+
+    // picks this drive with the top bits of `sector`, then gives it time to answer
+    unsafe fn select(&mut self, sector: u64) {
+        let drive = match self.drive {
+            Drive::Master => 0xe0,
+            Drive::Slave => 0xf0,
+        };
+        self.port(DRIVE_HEAD).write(drive | (sector >> 24) as u8 & 0x0f);
+        self.control().write(CONTROL_NO_INTERRUPTS);
+        self.delay();
+    }
+
+    // each read of the alternate status takes about 100ns, the drive needs 400ns to update it
+    unsafe fn delay(&mut self) {
+        for _ in 0..4 {
+            self.control().read();
+        }
+    }
+
+    unsafe fn wait_ready(&mut self) -> Result<u8, BlockError> {
+        for _ in 0..TIMEOUT {
+            let status = self.control().read();
+            if status & STATUS_BUSY == 0 {
+                return Ok(status);
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+
+    // until the drive wants the next sector moved, or it failed
+    unsafe fn wait_data(&mut self) -> Result<(), BlockError> {
+        for _ in 0..TIMEOUT {
+            let status = self.wait_ready()?;
+            if status & (STATUS_ERROR | STATUS_DEVICE_FAULT) != 0 {
+                return Err(BlockError::DeviceError(self.port(ERROR).read()));
+            }
+            if status & STATUS_DATA_REQUEST != 0 {
+                return Ok(());
+            }
+        }
+        Err(BlockError::Timeout)
+    }
+
+    // `count` is at most 256, which the register takes as 0
+    unsafe fn command(&mut self, command: u8, sector: u64, count: u64) -> Result<(), BlockError> {
+        self.wait_ready()?;
+        self.select(sector);
+        self.port(SECTOR_COUNT).write(count as u8);
+        self.port(LBA_LOW).write(sector as u8);
+        self.port(LBA_MID).write((sector >> 8) as u8);
+        self.port(LBA_HIGH).write((sector >> 16) as u8);
+        self.port(STATUS_COMMAND).write(command);
+        Ok(())
+    }
+}
+
+impl BlockDevice for AtaDisk {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read(&mut self, sector: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        let sectors = check_range(sector, buffer.len(), self.sectors)?;
+        let _bus = BUSES[self.bus as usize].lock();
+        let mut data = Port::<u16>::new(self.io_base() + DATA);
+
+        let mut done = 0;
+        while done < sectors {
+            let count = (sectors - done).min(SECTORS_PER_COMMAND);
+            unsafe { self.command(COMMAND_READ_SECTORS, sector + done, count)? };
+
+            for index in done..done + count {
+                unsafe { self.wait_data()? };
+                let start = index as usize * SECTOR_SIZE;
+                for bytes in buffer[start..start + SECTOR_SIZE].chunks_exact_mut(2) {
+                    bytes.copy_from_slice(&unsafe { data.read() }.to_le_bytes());
+                }
+            }
+            done += count;
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, sector: u64, data: &[u8]) -> Result<(), BlockError> {
+        let sectors = check_range(sector, data.len(), self.sectors)?;
+        let _bus = BUSES[self.bus as usize].lock();
+        let mut port = Port::<u16>::new(self.io_base() + DATA);
+
+        let mut done = 0;
+        while done < sectors {
+            let count = (sectors - done).min(SECTORS_PER_COMMAND);
+            unsafe { self.command(COMMAND_WRITE_SECTORS, sector + done, count)? };
+
+            for index in done..done + count {
+                unsafe { self.wait_data()? };
+                let start = index as usize * SECTOR_SIZE;
+                for bytes in data[start..start + SECTOR_SIZE].chunks_exact(2) {
+                    unsafe { port.write(u16::from_le_bytes([bytes[0], bytes[1]])) };
+                }
+            }
+            done += count;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        let _bus = BUSES[self.bus as usize].lock();
+        unsafe {
+            self.command(COMMAND_CACHE_FLUSH, 0, 0)?;
+            let status = self.wait_ready()?;
+            if status & (STATUS_ERROR | STATUS_DEVICE_FAULT) != 0 {
+                return Err(BlockError::DeviceError(self.port(ERROR).read()));
+            }
+        }
+        Ok(())
+    }
+}
@@ -6,11 +6,12 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
-use crate::{change_bg, change_fg, print, println, clear_screen, os_info::{self, OS_NAME}, task::{self, keyboard}, application::Application, vga_driver, asm, random};
+use crate::{change_bg, change_fg, print, println, clear_screen, os_info::{self, OS_NAME}, task::{self, keyboard, deferred}, application::Application, vga_driver, asm, random, fault, interrupts, memory::{self, protection, swap::{self, SwapError}}, allocator::{self, fixed_size_block::BLOCK_SIZES, slab}, block::ata::{AtaDisk, Bus, Drive}};
 use vga::colors::Color16;
-use alloc::{vec::Vec, boxed::Box, string::{String, ToString}};
+use alloc::{vec::Vec, boxed::Box, format, string::{String, ToString}};
 use lazy_static::lazy_static;
-use spin::Mutex;
+use x86_64::VirtAddr;
+use crate::lock::Mutex;
 
 lazy_static! {
     static ref COMMANDS: Mutex<Vec<&'static Command>> = Mutex::new(Vec::new());
@@ -114,6 +115,18 @@ pub fn init() {
     add_command(Command::new("tfrst", "Triple Fault Reset (MAY CORRUPT HARDWARE)", triple_fault_reset));
     add_command(Command::new("rnd", "Generates a random number", generate_rnd));
     add_command(Command::new("rndrg", "Generates a random number in a range", generate_rnd_range));
+    add_command(Command::new("irqstat", "Shows interrupt counts per vector", irq_stat));
+    add_command(Command::new("meminfo", "Shows physical and heap memory usage", mem_info));
+    add_command(Command::new("free", "Same as meminfo", mem_info));
+    add_command(Command::new("memmap", "Lists the memory regions reported at boot", mem_map));
+    add_command(Command::new("slabinfo", "Shows the slab caches and their occupancy", slab_info));
+    add_command(Command::new("vtop", "Shows the page table walk for a virtual address", virt_to_phys));
+    add_command(Command::new("audit", "Lists writable and executable mappings", audit));
+    add_command(Command::new("mkswap", "Formats an ATA disk for swap (1-3, primary slave by default)", make_swap));
+    add_command(Command::new("swapon", "Swaps to a disk formatted with mkswap (1-3, primary slave by default)", swap_on));
+    add_command(Command::new("swapoff", "Reads everything back from swap and stops swapping", swap_off));
+    add_command(Command::new("ps", "Lists the running tasks", list_tasks));
+    add_command(Command::new("tasks", "Same as ps", list_tasks));
     
     show_intro(false);
 }
@@ -158,28 +171,35 @@ pub(crate) fn process_command() {
     // split the command into arguments and keep it mutable
     let mut args = command.split(' ').collect::<Vec<&str>>();
     
-    let mut command_found = false;
-
-    for cmd in COMMANDS.lock().iter() {
-        if cmd.name == args[0] {
-            // cmd.function(&'static mut args);
+    /*****************************************************
+    * copy the function out so the command list isn't
+        locked while the command runs
+    *****************************************************/
+    let function = COMMANDS.lock()
+        .iter()
+        .find(|cmd| cmd.name == args[0])
+        .map(|cmd| cmd.function);
+
+    match function {
+        Some(function) => {
             let mut cmd_str = String::new();
             for arg in args.iter().skip(1) {
                 cmd_str.push_str(arg);
                 cmd_str.push(' ');
             }
 
-            (cmd.function)(&mut cmd_str);
-            command_found = true;
-            break;
+            if let Err(fault) = fault::catch(|| function(&mut cmd_str)) {
+                change_fg!(Color16::Red);
+                println!("Command \"{}\" failed: {}", args[0], fault);
+                change_fg!(Color16::White);
+            }
+        }
+        None => {
+            change_fg!(Color16::Red);
+            
+            println!("Command \"{}\" not found", args[0]);
+            change_fg!(Color16::White);
         }
-    }
-
-    if !command_found {
-        change_fg!(Color16::Red);
-        
-        println!("Command \"{}\" not found", args[0]);
-        change_fg!(Color16::White);
     }
 
     /******************************
@@ -192,10 +212,6 @@ pub(crate) fn process_command() {
 }
 
 fn help(_cmd: &mut String) {
-    unsafe {
-        COMMANDS.force_unlock();
-    }
-    
     println!("Commands:");
 
     for cmd in COMMANDS.lock().iter() {
@@ -226,6 +242,227 @@ fn generate_rnd_range(cmd: &mut String) {
     println!("Random number in range ({}, {}): {}", min, max, random::generate_rnd_rng(min, max));
 }
 
+fn irq_stat(_cmd: &mut String) {
+    println!("VECTOR  IRQ  COUNT       UNHANDLED  HANDLERS");
+
+    for stat in interrupts::irq_stats() {
+        if stat.count == 0 && stat.handlers.is_empty() {
+            continue;
+        }
+
+        let line = match stat.line {
+            Some(line) => line.to_string(),
+            None => "-".to_string(),
+        };
+        println!("{:<7} {:<4} {:<11} {:<10} {}", stat.vector, line, stat.count, stat.unhandled, stat.handlers.join(", "));
+    }
+
+    println!("NMIs: {}", interrupts::nmi_count());
+    let work = deferred::stats();
+    println!("Deferred work: {} scheduled, {} completed, {} dropped", work.scheduled, work.completed, work.dropped);
+}
+
+fn mem_info(_cmd: &mut String) {
+    if let Some(frames) = memory::frame_stats() {
+        println!("Physical memory:");
+        println!("  Total: {} KiB ({} frames)", frames.total_frames * 4, frames.total_frames);
+        println!("  Used:  {} KiB ({} frames)", frames.used_frames * 4, frames.used_frames);
+        println!("  Free:  {} KiB ({} frames)", frames.free_frames * 4, frames.free_frames);
+    }
+
+    match swap::stats() {
+        Some(swap) => println!("Swap on {}: {} of {} KiB used", swap.device, swap.used * 4, swap.slots * 4),
+        None => println!("Swap: off"),
+    }
+
+    let heap = allocator::heap_stats();
+    let allocator = allocator::allocator_stats();
+
+    /*****************************************************
+    * how much of the free heap is unusable for one big
+    	allocation, 0% means it's all one block
+    *****************************************************/
+    let fragmentation = if allocator.heap_free == 0 {
+        0
+    } else {
+        100 - allocator.largest_free_block * 100 / allocator.heap_free
+    };
+
+    println!("Heap ({} allocator):", allocator.name);
+    println!("  Mapped: {} KiB of {} KiB limit", heap.size / 1024, heap.limit / 1024);
+    println!("  In use: {} bytes in {} allocations ({} since boot)", allocator.bytes_in_use, allocator.allocations, allocator.total_allocations);
+    println!("  Free: {} of {} bytes", allocator.heap_free, allocator.heap_size);
+    println!("  Largest free block: {} bytes, {}% fragmented", allocator.largest_free_block, fragmentation);
+
+    if let Some(free_blocks) = allocator.free_blocks {
+        print!("  Free blocks:");
+        for (size, count) in BLOCK_SIZES.iter().zip(free_blocks.iter()) {
+            print!(" {}B={}", size, count);
+        }
+        println!();
+    }
+}
+
+fn mem_map(_cmd: &mut String) {
+    let memory_map = match memory::memory_map() {
+        Some(memory_map) => memory_map,
+        None => {
+            println!("Memory map not available");
+            return;
+        }
+    };
+
+    println!("START              END                SIZE       TYPE");
+    for region in memory_map.iter() {
+        let start = region.range.start_addr();
+        let end = region.range.end_addr();
+        println!("{:#018x} {:#018x} {:<6} KiB {:?}", start, end, (end - start) / 1024, region.region_type);
+    }
+}
+
+fn slab_info(_cmd: &mut String) {
+    let mut caches = Vec::new();
+    if let Some(size_classes) = allocator::size_class_caches() {
+        caches.extend_from_slice(&size_classes);
+    }
+    caches.extend(slab::object_cache_stats());
+
+    if caches.is_empty() {
+        println!("No slab caches in use");
+        return;
+    }
+
+    println!("NAME            SIZE  PER SLAB  PAGES  SLABS  FULL  EMPTY  IN USE");
+    for cache in caches {
+        println!("{:<15} {:>4}  {:>8}  {:>5}  {:>5}  {:>4}  {:>5}  {:>6}",
+            cache.name, cache.object_size, cache.objects_per_slab, cache.pages_per_slab,
+            cache.slabs, cache.full_slabs, cache.empty_slabs, cache.objects_in_use);
+    }
+}
+
+fn virt_to_phys(cmd: &mut String) {
+    let arg = cmd.split(' ').next().unwrap_or("");
+    if arg.is_empty() {
+        println!("Usage: vtop <addr>");
+        return;
+    }
+
+    let addr = match u64::from_str_radix(arg.trim_start_matches("0x"), 16).ok().and_then(|addr| VirtAddr::try_new(addr).ok()) {
+        Some(addr) => addr,
+        None => {
+            println!("\"{}\" is not a canonical virtual address", arg);
+            return;
+        }
+    };
+
+    let walk = memory::walk_page_tables(addr);
+    for step in walk.steps.iter().flatten() {
+        println!("P{}[{:>3}] in {:#012x} -> {:#012x} {:?}", step.level, step.index, step.table.as_u64(), step.addr.as_u64(), step.flags);
+    }
+
+    match walk.phys {
+        Some(phys) => println!("{:#x} -> {:#x} ({} KiB page)", addr.as_u64(), phys.as_u64(), walk.page_size / 1024),
+        None => println!("{:#x} is not mapped", addr.as_u64()),
+    }
+}
+
+fn audit(_cmd: &mut String) {
+    let enabled = protection::enabled();
+    let supported = protection::supported();
+    let state = |on: bool, available: bool| if on { "on" } else if available { "off" } else { "unsupported" };
+    println!("NX: {}, WP: {}, SMEP: {}, SMAP: {}",
+        state(enabled.no_execute, supported.no_execute), state(enabled.write_protect, supported.write_protect),
+        state(enabled.smep, supported.smep), state(enabled.smap, supported.smap));
+
+    let mappings = protection::writable_executable_mappings();
+    if mappings.is_empty() {
+        println!("No writable and executable mappings");
+        return;
+    }
+
+    println!("START              END                SIZE       OWNER");
+    change_fg!(Color16::Yellow);
+    for mapping in mappings {
+        let end = mapping.start.as_u64() + mapping.size;
+        let owner = if mapping.user { "user" } else { "kernel" };
+        println!("{:#018x} {:#018x} {:<6} KiB {}", mapping.start.as_u64(), end, mapping.size / 1024, owner);
+    }
+    change_fg!(Color16::White);
+}
+
+/*********************************************************
+* The disk a swap command names. The boot disk is the
+	primary master and is never offered.
+*********************************************************/
+fn swap_disk(cmd: &str, usage: &str) -> Option<AtaDisk> {
+    let (bus, drive) = match cmd.split(' ').next().unwrap_or("") {
+        "0" => {
+            println!("Disk 0 is the boot disk, it can't be used for swap");
+            return None;
+        }
+        "" | "1" => (Bus::Primary, Drive::Slave),
+        "2" => (Bus::Secondary, Drive::Master),
+        "3" => (Bus::Secondary, Drive::Slave),
+        _ => {
+            println!("Usage: {} [1-3]", usage);
+            return None;
+        }
+    };
+
+    match AtaDisk::open(bus, drive) {
+        Ok(disk) => Some(disk),
+        Err(error) => {
+            println!("No disk on the {:?} {:?}: {}", bus, drive, error);
+            None
+        }
+    }
+}
+
+fn make_swap(cmd: &mut String) {
+    let mut disk = match swap_disk(cmd, "mkswap") {
+        Some(disk) => disk,
+        None => return,
+    };
+
+    match swap::format(&mut disk) {
+        Ok(slots) => println!("Formatted {} for swap ({} KiB)", disk.model(), slots * 4),
+        Err(error) => println!("mkswap failed: {}", error),
+    }
+}
+
+fn swap_on(cmd: &mut String) {
+    let disk = match swap_disk(cmd, "swapon") {
+        Some(disk) => disk,
+        None => return,
+    };
+
+    let model = String::from(disk.model());
+    match swap::swapon(Box::new(disk)) {
+        Ok(slots) => println!("Swapping to {} ({} KiB)", model, slots * 4),
+        Err(SwapError::NoSignature) => println!("swapon failed: {} isn't formatted for swap, run mkswap first", model),
+        Err(error) => println!("swapon failed: {}", error),
+    }
+}
+
+fn swap_off(_cmd: &mut String) {
+    match swap::swapoff() {
+        Ok(()) => println!("Swap is off"),
+        Err(error) => println!("swapoff failed: {}", error),
+    }
+}
+
+fn list_tasks(_cmd: &mut String) {
+    println!("ID     NAME         STATE     PRIORITY   POLLS   CYCLES       MAX");
+    for task in task::registry::tasks() {
+        let name = task.name.as_deref().unwrap_or("-");
+        println!(
+            "{:<6} {:<12} {:<9} {:<10} {:<7} {:<12} {}",
+            task.id, name, format!("{:?}", task.state), format!("{:?}", task.priority),
+            task.polls, task.poll_cycles, task.max_poll_cycles,
+        );
+    }
+}
+
 fn rename_device(cmd: &mut String) {
     let args = cmd.split(' ').collect::<Vec<&str>>();
 
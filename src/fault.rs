@@ -0,0 +1,208 @@
+/**************************************************************************************************
+* Name : 									  fault.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				  Recovering from faults in shell commands
+* Version : 									 0.1
+**************************************************************************************************/
+
+use core::{arch::asm, fmt, ptr::addr_of_mut, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+use x86_64::{VirtAddr, instructions::interrupts, structures::idt::InterruptStackFrame};
+use crate::lock::{self, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    DivideError,
+    InvalidOpcode,
+    GeneralProtection,
+    PageFault,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub instruction_pointer: VirtAddr,
+    pub error_code: Option<u64>,
+    pub address: Option<VirtAddr>,
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            FaultKind::DivideError => "DIVIDE ERROR",
+            FaultKind::InvalidOpcode => "INVALID OPCODE",
+            FaultKind::GeneralProtection => "GENERAL PROTECTION FAULT",
+            FaultKind::PageFault => "PAGE FAULT",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:#x}", self.kind, self.instruction_pointer.as_u64())?;
+        if let Some(address) = self.address {
+            write!(f, ", accessed address {:#x}", address.as_u64())?;
+        }
+        if let Some(error_code) = self.error_code {
+            write!(f, ", error code {:#x}", error_code)?;
+        }
+        Ok(())
+    }
+}
+
+/****************************************
+* Where a recovered fault jumps back to.
+	Filled in by `call_with_recovery`,
+	the layout is used from assembly.
+****************************************/
+#[repr(C)]
+struct RecoveryPoint {
+    stack_pointer: u64,
+    resume_address: u64,
+}
+
+static RECOVERY_ACTIVE: AtomicBool = AtomicBool::new(false);
+static mut RECOVERY_POINT: RecoveryPoint = RecoveryPoint { stack_pointer: 0, resume_address: 0 };
+static LAST_FAULT: Mutex<Option<Fault>> = Mutex::new(None);
+
+// locks that were already taken when the command started
+static LOCKS_AT_CATCH: AtomicUsize = AtomicUsize::new(0);
+
+// how many interrupt handlers are running, their faults are the kernel's
+static KERNEL_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/**************************************************************************
+* Runs `function` so that CPU faults raised inside of it return here as an
+	error instead of halting the machine.
+* Nothing on the faulting call stack is dropped, so this is only meant for
+	running commands. A fault while `function` holds a lock isn't recovered.
+**************************************************************************/
+pub fn catch<F: FnOnce()>(function: F) -> Result<(), Fault> {
+    if RECOVERY_ACTIVE.swap(true, Ordering::SeqCst) {
+        // the outer recovery point handles faults of nested calls
+        function();
+        return Ok(());
+    }
+
+    LOCKS_AT_CATCH.store(lock::held(), Ordering::SeqCst);
+    let mut function = Some(function);
+    let faulted = unsafe {
+        call_with_recovery(run_closure::<F>, &mut function as *mut Option<F> as *mut u8)
+    };
+
+    RECOVERY_ACTIVE.store(false, Ordering::SeqCst);
+
+    if faulted {
+        let fault = interrupts::without_interrupts(|| LAST_FAULT.lock().take());
+        Err(fault.expect("recovered without a fault"))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn is_active() -> bool {
+    RECOVERY_ACTIVE.load(Ordering::SeqCst)
+}
+
+/*********************************************************
+* Held by interrupt handlers while they run. A fault then
+	is in the kernel, not in the command it interrupted,
+	and isn't recovered from.
+*********************************************************/
+pub(crate) struct KernelContext;
+
+impl KernelContext {
+    pub(crate) fn enter() -> Self {
+        KERNEL_DEPTH.fetch_add(1, Ordering::SeqCst);
+        KernelContext
+    }
+}
+
+impl Drop for KernelContext {
+    fn drop(&mut self) {
+        KERNEL_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/*********************************************************
+* Jumping back would leave every lock the command took
+	locked for good, so a fault with one of them taken
+	halts like any other kernel fault
+*********************************************************/
+fn locks_taken_since_catch() -> bool {
+    lock::held() != LOCKS_AT_CATCH.load(Ordering::SeqCst)
+}
+
+/*************************************************************
+* Called by exception handlers. Returns false if nothing can
+	recover from the fault, in which case the kernel must halt.
+	Only faults of the command itself are recovered from.
+*************************************************************/
+pub(crate) fn try_recover(stack_frame: &mut InterruptStackFrame, fault: Fault) -> bool {
+    if !is_active() || KERNEL_DEPTH.load(Ordering::SeqCst) > 0 || locks_taken_since_catch() {
+        return false;
+    }
+
+    *LAST_FAULT.lock() = Some(fault);
+
+    unsafe {
+        let stack_pointer = VirtAddr::new(RECOVERY_POINT.stack_pointer);
+        let resume_address = VirtAddr::new(RECOVERY_POINT.resume_address);
+        stack_frame.as_mut().update(|frame| {
+            frame.instruction_pointer = resume_address;
+            frame.stack_pointer = stack_pointer;
+        });
+    }
+
+    true
+}
+
+extern "C" fn run_closure<F: FnOnce()>(data: *mut u8) {
+    let function = unsafe { &mut *(data as *mut Option<F>) };
+    if let Some(function) = function.take() {
+        function();
+    }
+}
+
+/******************************************************************
+* Saves the callee-saved registers and a resume address, then calls
+	`function(data)`. Returns true if a fault handler resumed here.
+******************************************************************/
+unsafe fn call_with_recovery(function: extern "C" fn(*mut u8), data: *mut u8) -> bool {
+    let faulted: u64;
+
+    asm!(
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rsi], rsp",
+        "lea rcx, [rip + 2f]",
+        "mov [rsi + 8], rcx",
+        "mov rbp, rsp",
+        "and rsp, -16",
+        "call rdx",
+        "mov rsp, rbp",
+        "xor eax, eax",
+        "jmp 3f",
+        "2:",
+        "mov eax, 1",
+        "3:",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        in("rdi") data,
+        in("rsi") addr_of_mut!(RECOVERY_POINT),
+        in("rdx") function,
+        lateout("rax") faulted,
+        clobber_abi("C"),
+    );
+
+    faulted != 0
+}
@@ -6,50 +6,254 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
+use core::{ptr::{addr_of, addr_of_mut}, sync::atomic::{AtomicUsize, Ordering}};
 use x86_64::VirtAddr;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor};
 use x86_64::structures::gdt::SegmentSelector;
+use x86_64::structures::paging::{mapper::MapToError, FrameAllocator, Mapper, Page, Size4KiB};
 use lazy_static::lazy_static;
+use crate::memory::mapping::MapFlags;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const PAGE_FAULT_IST_INDEX: u16 = 1;
+pub const NMI_IST_INDEX: u16 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 3;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
+/*****************************************************************
+* The page fault handler swaps pages in and can fault again itself.
+	A nested fault would start over at the top of the stack the
+	handler is running on, so page faults get a stack per nesting
+	level and `PageFaultStack` moves the IST entry along.
+*****************************************************************/
+pub const PAGE_FAULT_STACKS: usize = 3;
+
+/*******************************************************
+* Stack the CPU switches to when an interrupt arrives in
+	ring 3, also used by the syscall entry point
+*******************************************************/
+pub const PRIVILEGE_STACK_INDEX: usize = 0;
+
+/*****************************************************************
+* The IST stacks start out as statics so exceptions work during
+	boot, `init_stacks` swaps them for mapped stacks that have an
+	unmapped guard page below them.
+*****************************************************************/
+pub const IST_STACKS_START: u64 = 0x_5555_0000_0000;
+pub const IST_STACK_PAGES: u64 = 5;
+
+pub const IST_STACK_COUNT: usize = 3 + PAGE_FAULT_STACKS;
+
+// the IST entry every stack belongs to, in the order they're mapped
+const IST_STACKS: [(u16, &str); IST_STACK_COUNT] = [
+    (DOUBLE_FAULT_IST_INDEX, "double fault"),
+    (PAGE_FAULT_IST_INDEX, "page fault"),
+    (PAGE_FAULT_IST_INDEX, "nested page fault"),
+    (PAGE_FAULT_IST_INDEX, "nested page fault"),
+    (NMI_IST_INDEX, "NMI"),
+    (MACHINE_CHECK_IST_INDEX, "machine check"),
+];
+const FIRST_PAGE_FAULT_STACK: usize = 1;
+const BOOT_STACK_SIZE: usize = 4096 * 5;
+
+static mut BOOT_STACKS: [[u8; BOOT_STACK_SIZE]; IST_STACK_COUNT] = [[0; BOOT_STACK_SIZE]; IST_STACK_COUNT];
+static mut STACK_TOPS: [VirtAddr; IST_STACK_COUNT] = [VirtAddr::zero(); IST_STACK_COUNT];
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+// how many page fault handlers are running
+static PAGE_FAULT_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy)]
+pub struct StackGuard {
+    pub name: &'static str,
+    pub guard_start: VirtAddr,
+    pub stack_top: VirtAddr,
+}
+
+impl StackGuard {
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.guard_start && addr < self.guard_start + 4096u64
+    }
+}
+
+static mut STACK_GUARDS: [Option<StackGuard>; IST_STACK_COUNT] = [None; IST_STACK_COUNT];
+
+fn init_tss() -> &'static TaskStateSegment {
+    unsafe {
+        for index in 0..IST_STACK_COUNT {
+            let stack_start = VirtAddr::from_ptr(addr_of!(BOOT_STACKS[index]));
+            set_stack_top(index, stack_start + BOOT_STACK_SIZE);
+        }
+        let tss = &mut *addr_of_mut!(TSS);
+        tss.privilege_stack_table[PRIVILEGE_STACK_INDEX] = {
             const STACK_SIZE: usize = 4096 * 5;
             static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
+            let stack_start = VirtAddr::from_ptr(addr_of!(STACK));
             let stack_end = stack_start + STACK_SIZE;
             stack_end
         };
-        tss
-    };
+        &*addr_of!(TSS)
+    }
 }
 
+/********************************************************************
+* The order of the segments is fixed by `syscall` and `sysret`: user
+	data has to come right before user code, kernel data right after
+	kernel code.
+********************************************************************/
 lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
-        (gdt, Selectors { code_selector, tss_selector })
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(init_tss()));
+        (gdt, Selectors { code_selector, data_selector, user_code_selector, user_data_selector, tss_selector })
     };
 }
 
-struct Selectors {
-    code_selector: SegmentSelector,
-    tss_selector: SegmentSelector,
+pub struct Selectors {
+    pub code_selector: SegmentSelector,
+    pub data_selector: SegmentSelector,
+    pub user_code_selector: SegmentSelector,
+    pub user_data_selector: SegmentSelector,
+    pub tss_selector: SegmentSelector,
 }
 
 pub fn init() {
     use x86_64::instructions::tables::load_tss;
-    use x86_64::instructions::segmentation::{CS, Segment};
+    use x86_64::instructions::segmentation::{CS, DS, ES, SS, Segment};
 
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code_selector);
+        SS::set_reg(GDT.1.data_selector);
+        DS::set_reg(GDT.1.data_selector);
+        ES::set_reg(GDT.1.data_selector);
         load_tss(GDT.1.tss_selector);
     }
-}
\ No newline at end of file
+}
+
+/***************************************************************
+* Maps a guarded stack for every IST stack and points the TSS at
+	them. Needs paging, so it runs once memory is initialized.
+***************************************************************/
+pub fn init_stacks(
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    for index in 0..IST_STACK_COUNT {
+        let guard_start = VirtAddr::new(IST_STACKS_START + index as u64 * (IST_STACK_PAGES + 1) * 4096);
+        let stack_bottom = guard_start + 4096u64;
+        let stack_top = stack_bottom + IST_STACK_PAGES * 4096;
+
+        let page_range = Page::range(
+            Page::<Size4KiB>::containing_address(stack_bottom),
+            Page::containing_address(stack_top),
+        );
+
+        for page in page_range {
+            let frame = frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)?;
+            let flags = MapFlags::KERNEL_DATA.page_table_flags();
+            unsafe {
+                mapper.map_to(page, frame, flags, frame_allocator)?.flush()
+            };
+        }
+
+        unsafe {
+            STACK_GUARDS[index] = Some(StackGuard {
+                name: IST_STACKS[index].1,
+                guard_start,
+                stack_top,
+            });
+            set_stack_top(index, stack_top);
+        }
+    }
+
+    Ok(())
+}
+
+/**************************************************************
+* Only the first stack of an entry goes into the TSS, the other
+	page fault stacks are picked by `PageFaultStack`. The CPU
+	reads the TSS on every interrupt, so this takes effect now.
+**************************************************************/
+unsafe fn set_stack_top(index: usize, stack_top: VirtAddr) {
+    let (ist_index, _) = IST_STACKS[index];
+    (*addr_of_mut!(STACK_TOPS))[index] = stack_top;
+    if index == 0 || IST_STACKS[index - 1].0 != ist_index {
+        (*addr_of_mut!(TSS)).interrupt_stack_table[ist_index as usize] = stack_top;
+    }
+}
+
+/*****************************************************************
+* Held by the page fault handler while it runs. A fault inside of
+	it lands on the next level's stack instead of the one in use,
+	the handler runs with interrupts off so nothing else moves it.
+*****************************************************************/
+pub struct PageFaultStack {
+    depth: usize,
+}
+
+impl PageFaultStack {
+    /*************************************************************
+    * None if the faults are nested deeper than there are stacks.
+    	The last one was overwritten then, so this one can't return.
+    *************************************************************/
+    pub fn enter() -> Option<PageFaultStack> {
+        let depth = PAGE_FAULT_DEPTH.fetch_add(1, Ordering::SeqCst);
+        if depth >= PAGE_FAULT_STACKS {
+            return None;
+        }
+
+        // the last level has nowhere to go, a fault there is caught above
+        if depth + 1 < PAGE_FAULT_STACKS {
+            unsafe { point_page_faults_at(depth + 1) };
+        }
+        Some(PageFaultStack { depth })
+    }
+
+    // 0 for a fault that didn't happen inside the handler
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl Drop for PageFaultStack {
+    fn drop(&mut self) {
+        unsafe { point_page_faults_at(self.depth) };
+        PAGE_FAULT_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+unsafe fn point_page_faults_at(depth: usize) {
+    let stack_top = (*addr_of!(STACK_TOPS))[FIRST_PAGE_FAULT_STACK + depth];
+    (*addr_of_mut!(TSS)).interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = stack_top;
+}
+
+pub fn page_fault_depth() -> usize {
+    PAGE_FAULT_DEPTH.load(Ordering::SeqCst)
+}
+
+/*************************************************
+* Returns the stack whose guard page `addr` is in
+*************************************************/
+pub fn stack_guard_hit(addr: VirtAddr) -> Option<StackGuard> {
+    unsafe { &*addr_of!(STACK_GUARDS) }
+        .iter()
+        .flatten()
+        .find(|guard| guard.contains(addr))
+        .copied()
+}
+
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
+}
+
+pub fn kernel_stack_top() -> VirtAddr {
+    unsafe { (*addr_of!(TSS)).privilege_stack_table[PRIVILEGE_STACK_INDEX] }
+}
@@ -6,19 +6,34 @@
 * Version : 									 0.1
 **************************************************************************************************/
 
-use crate::{change_fg, gdt, hlt_loop, println};
+use crate::{change_fg, fault::{self, Fault, FaultKind}, gdt, hlt_loop, lock::Mutex, memory::address_space, println};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
 use vga::colors::Color16;
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::{VirtAddr, instructions::interrupts, structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode}};
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-pub static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+pub static PICS: Mutex<ChainedPics> =
+    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
-pub static mut INTERRUPT_COUNT: spin::Mutex<u64> = spin::Mutex::new(0);
+static INTERRUPT_COUNT: AtomicU64 = AtomicU64::new(0);
+static NMI_COUNT: AtomicU64 = AtomicU64::new(0);
+static NEXT_HANDLER_ID: AtomicU64 = AtomicU64::new(1);
+
+/****************************************
+* Vectors drivers can register handlers
+	for, the first 16 are the PIC lines
+****************************************/
+pub const IRQ_VECTOR_START: u8 = PIC_1_OFFSET;
+pub const IRQ_VECTOR_END: u8 = 64;
+pub const IRQ_LINES: u8 = 16;
+
+const IRQ_VECTOR_COUNT: usize = (IRQ_VECTOR_END - IRQ_VECTOR_START) as usize;
+const MAX_HANDLERS_PER_VECTOR: usize = 4;
 
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
@@ -28,15 +43,79 @@ pub enum InterruptIndex {
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
-    fn as_usize(self) -> usize {
-        self as usize
+    pub fn irq_line(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    Handled,
+    NotHandled,
+}
+
+/*****************************************************************
+* Runs in interrupt context with interrupts disabled, so it must
+	not block, allocate or print. Do the bare minimum with the
+	device and hand the rest to `task::deferred::schedule`.
+*****************************************************************/
+pub type IrqHandler = fn(vector: u8) -> IrqReturn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandlerId {
+    vector: u8,
+    slot: usize,
+    id: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    InvalidLine(u8),
+    InvalidVector(u8),
+    VectorFull(u8),
+    NotRegistered,
+}
+
+#[derive(Clone, Copy)]
+struct Registration {
+    id: u64,
+    name: &'static str,
+    handler: IrqHandler,
+}
+
+pub struct IrqStat {
+    pub vector: u8,
+    pub line: Option<u8>,
+    pub count: u64,
+    pub unhandled: u64,
+    pub handlers: Vec<&'static str>,
+}
+
+type HandlerSlots = [Option<Registration>; MAX_HANDLERS_PER_VECTOR];
+
+const NO_HANDLERS: HandlerSlots = [None; MAX_HANDLERS_PER_VECTOR];
+const ZERO: AtomicU64 = AtomicU64::new(0);
+
+static HANDLERS: Mutex<[HandlerSlots; IRQ_VECTOR_COUNT]> =
+    Mutex::new([NO_HANDLERS; IRQ_VECTOR_COUNT]);
+static IRQ_COUNTS: [AtomicU64; IRQ_VECTOR_COUNT] = [ZERO; IRQ_VECTOR_COUNT];
+static UNHANDLED_COUNTS: [AtomicU64; IRQ_VECTOR_COUNT] = [ZERO; IRQ_VECTOR_COUNT];
+
+macro_rules! irq_stubs {
+    ($($vector:literal),* $(,)?) => {
+        [$(irq_stub::<$vector> as HandlerFunc),*]
+    };
+}
+
+static IRQ_STUBS: [HandlerFunc; IRQ_VECTOR_COUNT] = irq_stubs!(
+    32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+    48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
+);
+
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
@@ -47,10 +126,24 @@ lazy_static! {
                 .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
         }
 
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        for (index, stub) in IRQ_STUBS.iter().enumerate() {
+            idt[IRQ_VECTOR_START as usize + index].set_handler_fn(*stub);
+        }
 
-        idt.page_fault.set_handler_fn(page_fault_handler);
+        unsafe {
+            idt.page_fault
+                .set_handler_fn(page_fault_handler)
+                .set_stack_index(gdt::PAGE_FAULT_IST_INDEX);
+            idt.non_maskable_interrupt
+                .set_handler_fn(nmi_handler)
+                .set_stack_index(gdt::NMI_IST_INDEX);
+            idt.machine_check
+                .set_handler_fn(machine_check_handler)
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+        }
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
 
         idt
     };
@@ -58,74 +151,335 @@ lazy_static! {
 
 pub fn init_idt() {
     IDT.load();
+
+    register_irq(InterruptIndex::Timer.irq_line(), "timer", timer_interrupt)
+        .expect("failed to register the timer interrupt");
 }
 
-pub fn get_index() -> u64 {
+/**********************************************************************
+* Registers `handler` for a PIC line (0-15). Lines can be shared, every
+	handler on the line runs and the EOI is sent once they're done.
+	The line is unmasked on the PIC, and masked again once its last
+	handler is unregistered.
+**********************************************************************/
+pub fn register_irq(line: u8, name: &'static str, handler: IrqHandler) -> Result<HandlerId, IrqError> {
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine(line));
+    }
+
+    register_vector(IRQ_VECTOR_START + line, name, handler)
+}
+
+pub fn register_vector(vector: u8, name: &'static str, handler: IrqHandler) -> Result<HandlerId, IrqError> {
+    let index = vector_index(vector).ok_or(IrqError::InvalidVector(vector))?;
+
+    /*************************************************
+    * the dispatcher takes this lock too, so we can't
+    	be interrupted while holding it
+    *************************************************/
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slot = handlers[index]
+            .iter()
+            .position(|registration| registration.is_none())
+            .ok_or(IrqError::VectorFull(vector))?;
+
+        let id = NEXT_HANDLER_ID.fetch_add(1, Ordering::Relaxed);
+        handlers[index][slot] = Some(Registration { id, name, handler });
+        set_line_masked(vector, false);
+        Ok(HandlerId { vector, slot, id })
+    })
+}
+
+pub fn unregister(id: HandlerId) -> Result<(), IrqError> {
+    let index = vector_index(id.vector).ok_or(IrqError::InvalidVector(id.vector))?;
+
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        // the slot may have been reused since, only its own handler can be removed
+        match handlers[index].get_mut(id.slot) {
+            Some(registration) if registration.map(|registration| registration.id) == Some(id.id) => {
+                *registration = None;
+            }
+            _ => return Err(IrqError::NotRegistered),
+        }
+
+        if handlers[index].iter().all(|registration| registration.is_none()) {
+            set_line_masked(id.vector, true);
+        }
+        Ok(())
+    })
+}
+
+/******************************************************
+* Only touches vectors that belong to a PIC line. The
+	secondary PIC's lines also need the cascade line 2.
+	Called with interrupts disabled.
+******************************************************/
+fn set_line_masked(vector: u8, masked: bool) {
+    let line = match vector.checked_sub(IRQ_VECTOR_START) {
+        Some(line) if line < IRQ_LINES => line,
+        _ => return,
+    };
+
+    let mut pics = PICS.lock();
     unsafe {
-        *INTERRUPT_COUNT.lock()
+        let [mut primary, mut secondary] = pics.read_masks();
+        if line < 8 {
+            primary = set_mask_bit(primary, line, masked);
+        } else {
+            secondary = set_mask_bit(secondary, line - 8, masked);
+            if !masked {
+                primary = set_mask_bit(primary, 2, false);
+            }
+        }
+        pics.write_masks(primary, secondary);
+    }
+}
+
+fn set_mask_bit(mask: u8, bit: u8, masked: bool) -> u8 {
+    if masked {
+        mask | 1 << bit
+    } else {
+        mask & !(1 << bit)
+    }
+}
+
+pub fn irq_stats() -> Vec<IrqStat> {
+    let handlers = interrupts::without_interrupts(|| *HANDLERS.lock());
+
+    (0..IRQ_VECTOR_COUNT)
+        .map(|index| {
+            let vector = IRQ_VECTOR_START + index as u8;
+            IrqStat {
+                vector,
+                line: if (index as u8) < IRQ_LINES { Some(index as u8) } else { None },
+                count: IRQ_COUNTS[index].load(Ordering::Relaxed),
+                unhandled: UNHANDLED_COUNTS[index].load(Ordering::Relaxed),
+                handlers: handlers[index].iter().flatten().map(|registration| registration.name).collect(),
+            }
+        })
+        .collect()
+}
+
+fn vector_index(vector: u8) -> Option<usize> {
+    if (IRQ_VECTOR_START..IRQ_VECTOR_END).contains(&vector) {
+        Some((vector - IRQ_VECTOR_START) as usize)
+    } else {
+        None
+    }
+}
+
+extern "x86-interrupt" fn irq_stub<const VECTOR: u8>(_stack_frame: InterruptStackFrame) {
+    dispatch(VECTOR);
+}
+
+fn dispatch(vector: u8) {
+    let _kernel = fault::KernelContext::enter();
+    let index = (vector - IRQ_VECTOR_START) as usize;
+    IRQ_COUNTS[index].fetch_add(1, Ordering::Relaxed);
+
+    /***********************************************
+    * copy the handlers out so they can (un)register
+    	handlers themselves without deadlocking
+    ***********************************************/
+    let handlers = HANDLERS.lock()[index];
+
+    let mut handled = false;
+    for registration in handlers.iter().flatten() {
+        if (registration.handler)(vector) == IrqReturn::Handled {
+            handled = true;
+        }
+    }
+
+    if !handled {
+        UNHANDLED_COUNTS[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /***********************
+    * Send an EOI to the PIC
+    ***********************/
+    if index < IRQ_LINES as usize {
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(vector);
+        }
     }
 }
 
+pub fn get_index() -> u64 {
+    INTERRUPT_COUNT.load(Ordering::Relaxed)
+}
+
 extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
+    mut stack_frame: InterruptStackFrame,
     error_code: PageFaultErrorCode,
 ) {
     use x86_64::registers::control::Cr2;
 
+    let address = Cr2::read();
+    let stack = gdt::PageFaultStack::enter();
+
+    /************************************************
+    * an exception stack overflowing can't be blamed
+    	on the command that happens to be running
+    ************************************************/
+    if let Some(guard) = gdt::stack_guard_hit(address) {
+        change_fg!(Color16::Red);
+        println!("EXCEPTION: STACK OVERFLOW");
+        println!("The {} stack overflowed into its guard page at {:?}", guard.name, address);
+        println!("{:#?}", stack_frame);
+        change_fg!(Color16::White);
+        hlt_loop();
+    }
+
+    let stack = match stack {
+        Some(stack) => stack,
+        None => panic!("EXCEPTION: PAGE FAULT\nPage faults nested more than {} deep at {:?}\n{:#?}",
+            gdt::PAGE_FAULT_STACKS, address, stack_frame),
+    };
+
+    {
+        // faults while handling this one are the kernel's
+        let _kernel = fault::KernelContext::enter();
+
+        // a kernel mapping the active address space hasn't seen yet
+        if address_space::sync_kernel_entry(address) {
+            return;
+        }
+
+        // memory that is only backed on first touch, or copied on the first write
+        if address_space::handle_page_fault(address, error_code) {
+            return;
+        }
+    }
+
+    let fault = Fault {
+        kind: FaultKind::PageFault,
+        instruction_pointer: stack_frame.instruction_pointer,
+        error_code: Some(error_code.bits()),
+        address: Some(address),
+    };
+    // a fault in the handler itself is the kernel's too
+    if stack.depth() == 0 && fault::try_recover(&mut stack_frame, fault) {
+        return;
+    }
+
     change_fg!(Color16::Red);
     println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
+    if is_stack_overflow(&stack_frame, address, error_code) {
+        println!("Probable kernel stack overflow, the access is right next to the stack pointer");
+    }
+    println!("Accessed Address: {:?}", address);
     println!("Error Code: {:?}", error_code);
     println!("{:#?}", stack_frame);
     change_fg!(Color16::White);
     hlt_loop();
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    unsafe {
-        /*****************************
-        * Increase the interrupt count
-        *****************************/
-        *INTERRUPT_COUNT.lock() += 1;
-
-        /***********************
-        * Send an EOI to the PIC
-        ***********************/
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
+/********************************************************
+* The bootloader's kernel stack has a guard page we don't
+	know the address of, so guess from the stack pointer
+********************************************************/
+fn is_stack_overflow(stack_frame: &InterruptStackFrame, address: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        return false;
     }
+
+    let stack_pointer = stack_frame.stack_pointer.as_u64();
+    let address = address.as_u64();
+    address < stack_pointer.saturating_add(4096) && address >= stack_pointer.saturating_sub(4096)
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
-    use x86_64::instructions::port::Port;
+/*********************************************************
+* An NMI can come while any lock is held, printing could
+	wait forever for the writer. It is only counted.
+*********************************************************/
+extern "x86-interrupt" fn nmi_handler(_stack_frame: InterruptStackFrame) {
+    NMI_COUNT.fetch_add(1, Ordering::Relaxed);
+}
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
-    }
+pub fn nmi_count() -> u64 {
+    NMI_COUNT.load(Ordering::Relaxed)
+}
 
-    let mut keyboard = KEYBOARD.lock();
-    let mut port = Port::new(0x60);
-    let scancode: u8 = unsafe { port.read() };
-    crate::task::keyboard::add_scancode(scancode);
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    change_fg!(Color16::Red);
+    println!("EXCEPTION: MACHINE CHECK\n{:#?}", stack_frame);
+    change_fg!(Color16::White);
+    hlt_loop();
+}
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+extern "x86-interrupt" fn divide_error_handler(mut stack_frame: InterruptStackFrame) {
+    recover_or_halt(&mut stack_frame, FaultKind::DivideError, None);
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(mut stack_frame: InterruptStackFrame) {
+    recover_or_halt(&mut stack_frame, FaultKind::InvalidOpcode, None);
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    mut stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    recover_or_halt(&mut stack_frame, FaultKind::GeneralProtection, Some(error_code));
+}
+
+/********************************************
+* Hands the fault back to a running command,
+	or reports it and halts if there is none
+********************************************/
+fn recover_or_halt(stack_frame: &mut InterruptStackFrame, kind: FaultKind, error_code: Option<u64>) {
+    let fault = Fault {
+        kind,
+        instruction_pointer: stack_frame.instruction_pointer,
+        error_code,
+        address: None,
+    };
+    if fault::try_recover(stack_frame, fault) {
+        return;
+    }
+
+    change_fg!(Color16::Red);
+    println!("EXCEPTION: {}", kind);
+    if let Some(error_code) = error_code {
+        println!("Error Code: {:#x}", error_code);
     }
+    println!("{:#?}", stack_frame);
+    change_fg!(Color16::White);
+    hlt_loop();
+}
+
+fn timer_interrupt(_vector: u8) -> IrqReturn {
+    /*****************************
+    * Increase the interrupt count
+    *****************************/
+    INTERRUPT_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    IrqReturn::Handled
 }
 
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
     println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
+/*********************************************************
+* A fault that can't push its frame ends up here, like one
+	on a stack that ran into its guard page
+*********************************************************/
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
     _error_code: u64,
 ) -> ! {
+    use x86_64::registers::control::Cr2;
+
+    let address = Cr2::read();
+    if let Some(guard) = gdt::stack_guard_hit(address) {
+        panic!("EXCEPTION: STACK OVERFLOW\nThe {} stack overflowed into its guard page at {:?}\n{:#?}",
+            guard.name, address, stack_frame);
+    }
+    if is_stack_overflow(&stack_frame, address, PageFaultErrorCode::empty()) {
+        panic!("EXCEPTION: DOUBLE FAULT\nProbable kernel stack overflow at {:?}\n{:#?}", address, stack_frame);
+    }
     panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
 }
 
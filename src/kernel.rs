@@ -9,8 +9,8 @@
 extern crate alloc;
 
 use bootloader::BootInfo;
-use midas::{task::{executor::Executor, keyboard, Task}, cmd, asm, vga_driver};
-use crate::{memory::BootInfoFrameAllocator, println};
+use midas::{task::{executor::Executor, keyboard, deferred, Priority, Task}, cmd, asm, vga_driver};
+use crate::{memory::GlobalFrameAllocator, println};
 use x86_64::{structures::paging::OffsetPageTable, VirtAddr};
 
 pub static OS_NAME: &str = "MidAS";
@@ -20,14 +20,17 @@ pub static VERSION: &str = env!("CARGO_PKG_VERSION");
 pub fn main(
     _boot_info: &'static BootInfo,
     mapper: &mut OffsetPageTable,
-    frame_allocator: &mut BootInfoFrameAllocator,
+    frame_allocator: &mut GlobalFrameAllocator,
     phys_mem_offset: VirtAddr,
 ) {    
     println!("Boot successful!");
     asm::test_asm();
     
+    deferred::init();
+
     let mut executor = Executor::new();
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    executor.spawn(Task::named("deferred", deferred::run()));
+    executor.spawn(Task::named("keyboard", keyboard::print_keypresses()).with_priority(Priority::Input));
 
     /*************
     * VGA Graphics
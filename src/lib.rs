@@ -32,6 +32,10 @@ pub mod asm;
 pub mod vga_driver;
 pub mod random;
 pub mod text;
+pub mod fault;
+pub mod lock;
+pub mod syscall;
+pub mod block;
 
 use core::panic::PanicInfo;
 
@@ -66,13 +70,17 @@ pub fn test_panic_handler(info: &PanicInfo) -> ! {
     hlt_loop();
 }
 
+#[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
+    allocator::report_out_of_memory(layout);
     panic!("allocation error: {:?}", layout)
 }
 
 pub fn init() {
     interrupts::init_idt();
     gdt::init();
+    syscall::init();
+    task::keyboard::init();
     unsafe { interrupts::PICS.lock().initialize() };
     x86_64::instructions::interrupts::enable();
 }
@@ -0,0 +1,70 @@
+/**************************************************************************************************
+* Name : 									   lock.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					 Spin Locks Fault Recovery can Count
+* Version : 									 0.1
+**************************************************************************************************/
+
+use core::{ops::{Deref, DerefMut}, sync::atomic::{AtomicUsize, Ordering}};
+
+// how many of the kernel's locks are taken right now
+static HELD: AtomicUsize = AtomicUsize::new(0);
+
+/*********************************************************
+* A spin::Mutex that keeps count of the locks taken. Fault
+	recovery jumps over the guards on the faulting stack,
+	so it only does that when no lock was taken since the
+	command started. Kernel state goes behind these.
+*********************************************************/
+pub struct Mutex<T: ?Sized> {
+    inner: spin::Mutex<T>,
+}
+
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    guard: spin::MutexGuard<'a, T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex { inner: spin::Mutex::new(value) }
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let guard = self.inner.lock();
+        HELD.fetch_add(1, Ordering::SeqCst);
+        MutexGuard { guard }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let guard = self.inner.try_lock()?;
+        HELD.fetch_add(1, Ordering::SeqCst);
+        Some(MutexGuard { guard })
+    }
+}
+
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        HELD.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn held() -> usize {
+    HELD.load(Ordering::SeqCst)
+}
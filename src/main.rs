@@ -17,7 +17,7 @@ mod serial;
 mod kernel;
 
 use core::{panic::PanicInfo, fmt::Write};
-use midas::{memory::{BootInfoFrameAllocator, self}, self, allocator, text::WRITER};
+use midas::{memory::{GlobalFrameAllocator, self}, self, allocator, gdt, text::WRITER};
 use midas::{println,change_color};
 use vga::colors::Color16;
 use x86_64::{VirtAddr};
@@ -65,12 +65,16 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     let mut mapper = unsafe {
         memory::init(phys_mem_offset)
     };
-    let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    unsafe {
+        memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset)
     };
+    memory::protection::init();
+    let mut frame_allocator = GlobalFrameAllocator;
 
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    gdt::init_stacks(&mut mapper, &mut frame_allocator)
+        .expect("exception stack initialization failed");
 
     #[cfg(test)]
     test_main();
@@ -9,43 +9,101 @@
 use x86_64::{
     PhysAddr,
     VirtAddr,
-    structures::paging::{PageTable, OffsetPageTable, Size4KiB, page_table::FrameError, FrameAllocator, Page, PageTableFlags as Flags, PhysFrame, Mapper, frame},
+    structures::paging::{PageTable, OffsetPageTable, Size4KiB, FrameAllocator, FrameDeallocator, Page, PageTableFlags as Flags, PhysFrame, Mapper, frame},
     registers::control::Cr3,
 };
-use bootloader::bootinfo::{MemoryRegionType, MemoryMap};
+use bootloader::bootinfo::MemoryMap;
+use crate::lock::Mutex;
+use core::sync::atomic::{AtomicU64, Ordering};
+use bitmap::BitmapFrameAllocator;
 
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
-    next: usize,
+pub mod bitmap;
+pub mod mapping;
+pub mod address_space;
+pub mod protection;
+pub mod dma;
+pub mod swap;
+
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+static KERNEL_LEVEL_4_TABLE: AtomicU64 = AtomicU64::new(0);
+
+pub static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+
+pub unsafe fn init_frame_allocator(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) {
+    let allocator = BitmapFrameAllocator::init(memory_map, physical_memory_offset);
+    *FRAME_ALLOCATOR.lock() = Some(allocator);
 }
 
-impl BootInfoFrameAllocator {
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        let regions = self.memory_map.iter();
-        let usable_regions = regions
-            .filter(|r| r.region_type == MemoryRegionType::Usable);
-        
-        let addr_ranges = usable_regions
-            .map(|r| r.range.start_addr()..r.range.end_addr());
-        
-        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
+/*********************************************************
+* Runs `f` on the kernel's frame allocator. Interrupts are
+	off meanwhile so a handler can't deadlock on the lock.
+*********************************************************/
+pub fn with_frame_allocator<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut BitmapFrameAllocator) -> R,
+{
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        f(allocator.as_mut().expect("frame allocator not initialized"))
+    })
+}
 
-        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
-    }
+/*********************************************************
+* `count` frames in a row for callers that can do without
+	them, None if the frame allocator isn't up yet
+*********************************************************/
+pub fn allocate_contiguous_frames(count: usize, align: usize, limit: Option<PhysAddr>)
+    -> Option<frame::PhysFrameRange>
+{
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_mut()?.allocate_contiguous(count, align, limit)
+    })
+}
 
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
-            next: 0,
-        }
-    }
+pub unsafe fn deallocate_contiguous_frames(range: frame::PhysFrameRange) {
+    with_frame_allocator(|allocator| allocator.deallocate_contiguous(range))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub used_frames: usize,
+    pub free_frames: usize,
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+pub fn frame_stats() -> Option<FrameStats> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_ref().map(|allocator| FrameStats {
+            total_frames: allocator.total_frames(),
+            used_frames: allocator.used_frames(),
+            free_frames: allocator.free_frames(),
+        })
+    })
+}
+
+pub fn memory_map() -> Option<&'static MemoryMap> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_ref().map(|allocator| allocator.memory_map())
+    })
+}
+
+/**********************************************************
+* A handle to `FRAME_ALLOCATOR` for everything that takes
+	a `FrameAllocator` or `FrameDeallocator`
+**********************************************************/
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            FRAME_ALLOCATOR.lock().as_mut()?.allocate_frame()
+        })
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        with_frame_allocator(|allocator| allocator.deallocate_frame(frame))
     }
 }
 
@@ -62,6 +120,9 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
 }
 
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
+    KERNEL_LEVEL_4_TABLE.store(Cr3::read().0.start_address().as_u64(), Ordering::Relaxed);
+
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
@@ -75,43 +136,125 @@ pub unsafe fn translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr)
 fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
     -> Option<PhysAddr>
 {
+    walk_with_offset(addr, physical_memory_offset).phys
+}
+
+/*********************************************************
+* One level of a page table walk: the entry at `index` in
+	the table at `table`, pointing at `addr`
+*********************************************************/
+#[derive(Debug, Clone, Copy)]
+pub struct WalkStep {
+    pub level: u8,
+    pub index: u16,
+    pub table: PhysAddr,
+    pub addr: PhysAddr,
+    pub flags: Flags,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PageWalk {
+    pub steps: [Option<WalkStep>; 4],
+    pub phys: Option<PhysAddr>,
+    pub page_size: u64,
+}
+
+/*********************************************************
+* Walks the active page tables for `addr`, stopping at a
+	missing entry or a 1 GiB / 2 MiB page
+*********************************************************/
+pub fn walk_page_tables(addr: VirtAddr) -> PageWalk {
+    walk_with_offset(addr, physical_memory_offset())
+}
+
+fn walk_with_offset(addr: VirtAddr, physical_memory_offset: VirtAddr) -> PageWalk {
     let (level_4_table_frame, _) = Cr3::read();
 
     let table_indexes = [
         addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()
     ];
-    let mut frame = level_4_table_frame;
+    let mut walk = PageWalk { steps: [None; 4], phys: None, page_size: 4096 };
+    let mut table_addr = level_4_table_frame.start_address();
 
-    for &index in &table_indexes {
-        let virt = physical_memory_offset + frame.start_address().as_u64();
+    for (depth, &index) in table_indexes.iter().enumerate() {
+        let virt = physical_memory_offset + table_addr.as_u64();
         let table_ptr: *const PageTable = virt.as_ptr();
         let table = unsafe { &*table_ptr };
 
         let entry = &table[index];
-        frame = match entry.frame() {
-            Ok(frame) => frame,
-            Err(FrameError::FrameNotPresent) => return None,
-            Err(FrameError::HugeFrame) => panic!("huge pages not supported"),
-        };
+        let level = 4 - depth as u8;
+        walk.steps[depth] = Some(WalkStep {
+            level,
+            index: u16::from(index),
+            table: table_addr,
+            addr: entry.addr(),
+            flags: entry.flags(),
+        });
+
+        if !entry.flags().contains(Flags::PRESENT) {
+            return walk;
+        }
+
+        // a huge page ends the walk early, the rest of the address is the offset into it
+        let huge = entry.flags().contains(Flags::HUGE_PAGE) && (level == 3 || level == 2);
+        if huge || level == 1 {
+            walk.page_size = match level {
+                3 => 1024 * 1024 * 1024,
+                2 => 2 * 1024 * 1024,
+                _ => 4096,
+            };
+            walk.phys = Some(entry.addr() + (addr.as_u64() & (walk.page_size - 1)));
+            return walk;
+        }
+
+        table_addr = entry.addr();
     }
 
-    Some(frame.start_address() + u64::from(addr.page_offset()))
+    walk
 }
 
-pub fn create_example_mapping(
-    page: Page,
-    mapper: &mut OffsetPageTable,
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
-)
-{
-    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
-    let flags = Flags::PRESENT | Flags::WRITABLE;
+/***********************************************************
+* A mapper for whatever page table is active right now, for
+	code that can't be handed the one made at boot
+***********************************************************/
+pub unsafe fn active_mapper() -> OffsetPageTable<'static> {
+    let physical_memory_offset = physical_memory_offset();
+    OffsetPageTable::new(active_level_4_table(physical_memory_offset), physical_memory_offset)
+}
 
-    let map_to_result = unsafe {
-        // TODO: replace. this is only for testing
-        mapper.map_to(page, frame, flags, frame_allocator)
-    };
-    map_to_result.expect("map_to failed").flush();
+/***********************************************************
+* A mapper for the kernel's own page table, the one active
+	at boot. Kernel mappings go here even while an address
+	space is active, which picks them up from it.
+***********************************************************/
+pub unsafe fn kernel_mapper() -> OffsetPageTable<'static> {
+    let physical_memory_offset = physical_memory_offset();
+    let virt = physical_memory_offset + kernel_level_4_frame().start_address().as_u64();
+    OffsetPageTable::new(&mut *virt.as_mut_ptr::<PageTable>(), physical_memory_offset)
+}
+
+pub fn kernel_level_4_frame() -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(KERNEL_LEVEL_4_TABLE.load(Ordering::Relaxed)))
+}
+
+pub fn physical_memory_offset() -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
+/*************************************************************
+* Checks that ring 3 may access `addr` in the active page
+	tables, every level has to allow it for the CPU to agree
+*************************************************************/
+pub fn user_accessible(addr: VirtAddr, write: bool) -> bool {
+    let walk = walk_page_tables(addr);
+    if walk.phys.is_none() {
+        return false;
+    }
+
+    walk.steps.iter().flatten().all(|step| {
+        step.flags.contains(Flags::PRESENT | Flags::USER_ACCESSIBLE)
+            && (!write || step.flags.contains(Flags::WRITABLE))
+    })
 }
 
 /***************************************
@@ -119,7 +262,13 @@ pub fn create_example_mapping(
 ***************************************/
 pub fn create_page(virt_addr: u64, mapper: &mut OffsetPageTable, frame_allocator: &mut impl FrameAllocator<Size4KiB>) -> Page {
     let page = Page::containing_address(VirtAddr::new(virt_addr));
-    create_example_mapping(page, mapper, frame_allocator);
+    let frame = frame_allocator.allocate_frame().expect("no frame left for the page");
+    let flags = mapping::MapFlags::KERNEL_DATA.page_table_flags();
+
+    unsafe {
+        core::ptr::write_bytes((physical_memory_offset() + frame.start_address().as_u64()).as_mut_ptr::<u8>(), 0, 4096);
+        mapper.map_to(page, frame, flags, frame_allocator).expect("map_to failed").flush();
+    }
 
     page
 }
@@ -133,9 +282,10 @@ pub fn write_page(page: Page, offset: isize, data: u8) {
     write_page_ptr(ptr, offset, data)
 }
 
+// fills the u64 at `offset` with `data`
 pub fn write_page_ptr(ptr: *mut u64, offset: isize, data: u8) {
     unsafe {
-        ptr.offset(offset).write_bytes(data, data as usize);
+        ptr.offset(offset).write_bytes(data, 1);
     }
 }
 
@@ -0,0 +1,840 @@
+/**************************************************************************************************
+* Name : 							   memory/address_space.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				  Address Spaces with Private User Mappings
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{
+    kernel_level_4_frame, physical_memory_offset, GlobalFrameAllocator,
+    mapping::{self, MapError, MapFlags},
+    swap::{self, Owner, SwapError},
+};
+use crate::{allocator::slab::{CacheBox, ObjectCache}, lock::Mutex};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use x86_64::{
+    instructions::tlb,
+    registers::control::Cr3,
+    structures::{
+        idt::PageFaultErrorCode,
+        paging::{
+            mapper::{MappedFrame, MapperFlush, TranslateResult, UnmapError},
+            FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags,
+            page_table::PageTableEntry, PhysFrame, Size4KiB, Translate,
+        },
+    },
+    PhysAddr, VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+// pages sent to swap when a frame is needed and there is none
+const RECLAIM_PAGES: usize = 16;
+
+/*********************************************************
+* The part of every address space that is its own, level
+	4 entries 64 to 127. The other entries are copied from
+	the kernel's table, so the kernel's mappings below them
+	are shared by all address spaces.
+*********************************************************/
+pub const USER_SPACE_START: u64 = 0x_2000_0000_0000;
+pub const USER_SPACE_END: u64 = 0x_4000_0000_0000;
+
+const USER_ENTRIES_START: usize = (USER_SPACE_START >> 39) as usize;
+const USER_ENTRIES_END: usize = (USER_SPACE_END >> 39) as usize;
+
+// tables above user pages have to allow everything their pages might
+const USER_TABLE_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::USER_ACCESSIBLE);
+
+/*********************************************************
+* Set on read-only pages of a writable area whose frame
+	is shared, the first write gets a copy of its own
+*********************************************************/
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+/*********************************************************
+* The address space in CR3, if it isn't the kernel's. It
+	is kept alive here until something else is switched to.
+*********************************************************/
+static CURRENT: Mutex<Option<Arc<AddressSpace>>> = Mutex::new(None);
+
+/*********************************************************
+* References to frames mapped in more than one place,
+	not counting the first. Frames not in here have one
+	owner and are freed once it lets go of them.
+*********************************************************/
+static SHARED_FRAMES: Mutex<BTreeMap<PhysFrame, usize>> = Mutex::new(BTreeMap::new());
+
+/*********************************************************
+* Every table of user space, level 4 included. They are
+	slab objects, so `slabinfo` shows how many are in use.
+*********************************************************/
+static PAGE_TABLES: ObjectCache<PageTable> = ObjectCache::new("page-tables");
+
+/*********************************************************
+* A range of user space and what it may be used for. Its
+	pages get frames when they're first touched.
+*********************************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub flags: MapFlags,
+}
+
+impl Area {
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    pub fn size(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    fn pages(&self) -> impl Iterator<Item = Page> {
+        mapping::page_range(self.start, self.size())
+    }
+}
+
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    state: Mutex<State>,
+}
+
+struct State {
+    areas: BTreeMap<u64, Area>,
+    // where the search for pages to swap out goes on from
+    hand: VirtAddr,
+}
+
+impl State {
+    fn area(&self, addr: VirtAddr) -> Option<Area> {
+        self.areas
+            .range(..=addr.as_u64())
+            .next_back()
+            .map(|(_, area)| *area)
+            .filter(|area| area.contains(addr))
+    }
+
+    fn add_area(&mut self, area: Area) -> Result<(), MapError> {
+        let overlapping = self.areas.range(..area.end.as_u64()).next_back();
+        if let Some((_, other)) = overlapping.filter(|(_, other)| other.end > area.start) {
+            return Err(MapError::AlreadyMapped(other.start.max(area.start)));
+        }
+
+        self.areas.insert(area.start.as_u64(), area);
+        Ok(())
+    }
+
+    // cuts [start, end) out of every area, splitting those it is in the middle of
+    fn remove_areas(&mut self, start: VirtAddr, end: VirtAddr) {
+        let overlapping: Vec<Area> = self.areas
+            .values()
+            .filter(|area| area.start < end && area.end > start)
+            .copied()
+            .collect();
+
+        for area in overlapping {
+            self.areas.remove(&area.start.as_u64());
+            if area.start < start {
+                self.areas.insert(area.start.as_u64(), Area { end: start, ..area });
+            }
+            if area.end > end {
+                self.areas.insert(end.as_u64(), Area { start: end, ..area });
+            }
+        }
+    }
+}
+
+impl AddressSpace {
+    /*****************************************************
+    * A new level 4 table with the kernel's entries and
+    	nothing mapped in user space
+    *****************************************************/
+    pub fn new() -> Result<AddressSpace, MapError> {
+        let level_4_frame = allocate_table().ok_or(MapError::OutOfFrames)?;
+
+        let space = AddressSpace {
+            level_4_frame,
+            state: Mutex::new(State { areas: BTreeMap::new(), hand: VirtAddr::new(USER_SPACE_START) }),
+        };
+        space.sync_kernel_entries();
+        Ok(space)
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    // pages that have a frame right now
+    pub fn resident_pages(&self) -> usize {
+        let _state = self.state.lock();
+        self.count_entries(|entry| entry.flags().contains(PageTableFlags::PRESENT))
+    }
+
+    pub fn swapped_pages(&self) -> usize {
+        let _state = self.state.lock();
+        self.count_entries(|entry| swap::slot(entry).is_some())
+    }
+
+    // the page table entries of user pages that `f` is true for
+    fn count_entries<F: Fn(&PageTableEntry) -> bool>(&self, f: F) -> usize {
+        fn count<'a, F>(entries: impl Iterator<Item = &'a PageTableEntry>, level: u8, f: &F) -> usize
+        where
+            F: Fn(&PageTableEntry) -> bool,
+        {
+            entries.map(|entry| match entry.frame() {
+                Ok(frame) if level > 1 => count(unsafe { table(frame) }.iter(), level - 1, f),
+                _ if level == 1 && f(entry) => 1,
+                _ => 0,
+            }).sum()
+        }
+
+        let level_4 = unsafe { table(self.level_4_frame) };
+        count(level_4.iter().take(USER_ENTRIES_END).skip(USER_ENTRIES_START), 4, &f)
+    }
+
+    pub fn area(&self, addr: VirtAddr) -> Option<Area> {
+        self.state.lock().area(addr)
+    }
+
+    pub fn areas(&self) -> Vec<Area> {
+        self.state.lock().areas.values().copied().collect()
+    }
+
+    /*****************************************************
+    * Adds an area over the pages covering `size` bytes at
+    	`start`. Its pages are zeroed frames, but only from
+    	the first time they're touched.
+    *****************************************************/
+    pub fn reserve(&self, start: VirtAddr, size: usize, flags: MapFlags) -> Result<(), MapError> {
+        let area = user_area(start, size, flags)?;
+        self.state.lock().add_area(area)
+    }
+
+    /*****************************************************
+    * Same as `reserve`, but every page gets its frame
+    	right away. Nothing stays mapped if it fails halfway.
+    *****************************************************/
+    pub fn map(&self, start: VirtAddr, size: usize, flags: MapFlags) -> Result<(), MapError> {
+        let area = user_area(start, size, flags)?;
+
+        let mut state = self.state.lock();
+        let mut mapper = unsafe { self.mapper() };
+        state.add_area(area)?;
+
+        for page in area.pages() {
+            if let Err(error) = self.populate(&mut mapper, &mut state, page.start_address(), false) {
+                let _ = self.unmap_pages(&mut mapper, &mut state, area.start, area.end);
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /*****************************************************
+    * Removes the pages covering `size` bytes at `start`
+    	from their areas and lets go of their frames. Pages
+    	without an area are reported once the rest are gone.
+    *****************************************************/
+    pub fn unmap(&self, start: VirtAddr, size: usize) -> Result<(), MapError> {
+        check_user_range(start, size)?;
+        let first = Page::<Size4KiB>::containing_address(start).start_address();
+        let end = first + mapping::page_range(start, size).count() as u64 * PAGE_SIZE;
+
+        let mut state = self.state.lock();
+        let mut mapper = unsafe { self.mapper() };
+        self.unmap_pages(&mut mapper, &mut state, first, end)
+    }
+
+    fn unmap_pages(&self, mapper: &mut OffsetPageTable<'static>, state: &mut State, start: VirtAddr, end: VirtAddr)
+        -> Result<(), MapError>
+    {
+        let mut first_error = None;
+
+        for page in mapping::page_range(start, (end - start) as usize) {
+            if state.area(page.start_address()).is_none() {
+                first_error.get_or_insert(MapError::NotMapped(page.start_address()));
+                continue;
+            }
+
+            // in swap, there is only the slot to give back
+            if let Some(entry) = unsafe { leaf_entry(self.level_4_frame, page.start_address()) } {
+                if let Some(slot) = swap::slot(entry) {
+                    swap::free_slot(slot);
+                    entry.set_unused();
+                    continue;
+                }
+            }
+
+            match mapper.unmap(page) {
+                Ok((frame, flush)) => {
+                    self.flush(flush);
+                    release_frame(frame);
+                }
+                // never touched
+                Err(UnmapError::PageNotMapped) => {}
+                Err(UnmapError::ParentEntryHugePage) => {
+                    first_error.get_or_insert(MapError::HugePage(page.start_address()));
+                }
+                Err(UnmapError::InvalidFrameAddress(_)) => {
+                    first_error.get_or_insert(MapError::NotMapped(page.start_address()));
+                }
+            }
+        }
+        state.remove_areas(start, end);
+
+        match first_error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /*****************************************************
+    * A copy of this address space. Their frames are
+    	shared until one of them writes to a page, which
+    	then gets a copy of its own.
+    *****************************************************/
+    pub fn fork(&self) -> Result<AddressSpace, MapError> {
+        let child = AddressSpace::new()?;
+
+        let result = {
+            let mut state = self.state.lock();
+            let mut child_state = child.state.lock();
+            let result = self.share_pages(&mut state, &child, &mut child_state);
+
+            // some pages may be copy on write now even if it failed
+            if self.is_active() {
+                tlb::flush_all();
+            }
+            result
+        };
+
+        // a half made child frees what it got when it's dropped
+        result.map(|_| child)
+    }
+
+    fn share_pages(&self, state: &mut State, child: &AddressSpace, child_state: &mut State) -> Result<(), MapError> {
+        let mut mapper = unsafe { self.mapper() };
+        let mut child_mapper = unsafe { child.mapper() };
+        let areas: Vec<Area> = state.areas.values().copied().collect();
+
+        for area in areas {
+            child_state.areas.insert(area.start.as_u64(), area);
+
+            for page in area.pages() {
+                // pages in swap are read back to share them like the rest
+                let swapped = unsafe { leaf_entry(self.level_4_frame, page.start_address()) }
+                    .map_or(false, |entry| swap::slot(entry).is_some());
+                if swapped {
+                    self.populate(&mut mapper, state, page.start_address(), false)?;
+                }
+
+                let (frame, mut flags) = match mapper.translate(page.start_address()) {
+                    TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+                    _ => continue,
+                };
+
+                if flags.contains(PageTableFlags::WRITABLE) {
+                    flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+                    unsafe { mapper.update_flags(page, flags) }
+                        .map(MapperFlush::ignore)
+                        .map_err(|_| MapError::NotMapped(page.start_address()))?;
+                }
+
+                unsafe { child_mapper.map_to_with_table_flags(page, frame, flags, USER_TABLE_FLAGS, &mut TableAllocator) }
+                    .map(MapperFlush::ignore)
+                    .map_err(|error| mapping::map_error(error, page.start_address()))?;
+                share_frame(frame);
+            }
+        }
+
+        Ok(())
+    }
+
+    /*****************************************************
+    * Makes the page at `addr` usable the way its area
+    	says, for writing if `write` is set. Returns whether
+    	anything had to be changed for that.
+    *****************************************************/
+    fn populate(&self, mapper: &mut OffsetPageTable<'static>, state: &mut State, addr: VirtAddr, write: bool)
+        -> Result<bool, MapError>
+    {
+        let area = state.area(addr).ok_or(MapError::NotMapped(addr))?;
+        if write && !area.flags.writable {
+            return Err(MapError::NotWritable(addr));
+        }
+
+        let page = Page::<Size4KiB>::containing_address(addr);
+        let flags = MapFlags { user: true, ..area.flags }.page_table_flags();
+
+        match mapper.translate(addr) {
+            TranslateResult::NotMapped => {
+                if let Some(entry) = unsafe { leaf_entry(self.level_4_frame, addr) } {
+                    if let Some(slot) = swap::slot(entry) {
+                        let frame = self.allocate_frame(state).ok_or(MapError::OutOfFrames)?;
+                        if swap::read_in(slot, frame).is_err() {
+                            unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+                            return Err(MapError::NotMapped(addr));
+                        }
+                        swap::set_restored(entry, frame);
+
+                        // it comes back the way it went, a write may still have to copy it
+                        self.populate(mapper, state, addr, write)?;
+                        return Ok(true);
+                    }
+                }
+
+                let frame = self.allocate_frame(state).ok_or(MapError::OutOfFrames)?;
+                mapping::zero_frame(frame);
+                unsafe { mapper.map_to_with_table_flags(page, frame, flags, USER_TABLE_FLAGS, &mut TableAllocator) }
+                    // nothing was cached for a page that wasn't mapped
+                    .map(MapperFlush::ignore)
+                    .map_err(|error| {
+                        unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+                        mapping::map_error(error, addr)
+                    })?;
+                Ok(true)
+            }
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags: current, .. } => {
+                if !write || current.contains(PageTableFlags::WRITABLE) {
+                    return Ok(false);
+                }
+                if !current.contains(COPY_ON_WRITE) {
+                    return Err(MapError::NotWritable(addr));
+                }
+
+                /*************************************************
+                * the last one to write to a shared frame can
+                	keep it, everyone else copies it first. The
+                	copy is made while the frame is still shared,
+                	so swapping for it can't pick this frame.
+                *************************************************/
+                if is_shared(frame) {
+                    let copy = self.allocate_frame(state).ok_or(MapError::OutOfFrames)?;
+                    unshare_frame(frame);
+                    unsafe { copy_frame(frame, copy) };
+
+                    let (_, flush) = mapper.unmap(page).map_err(|_| MapError::NotMapped(addr))?;
+                    flush.ignore();
+                    unsafe { mapper.map_to_with_table_flags(page, copy, flags, USER_TABLE_FLAGS, &mut TableAllocator) }
+                        .map(|flush| self.flush(flush))
+                        .map_err(|error| mapping::map_error(error, addr))?;
+                } else {
+                    unsafe { mapper.update_flags(page, flags) }
+                        .map(|flush| self.flush(flush))
+                        .map_err(|_| MapError::NotMapped(addr))?;
+                }
+                Ok(true)
+            }
+            _ => Err(MapError::HugePage(addr)),
+        }
+    }
+
+    /*****************************************************
+    * Called for faults in user space while this address
+    	space is active. False if the access isn't allowed,
+    	or memory ran out.
+    *****************************************************/
+    fn handle_fault(&self, addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+        // whatever held the lock faulted, it can't be resolved
+        let mut state = match self.state.try_lock() {
+            Some(state) => state,
+            None => return false,
+        };
+
+        let area = match state.area(addr) {
+            Some(area) => area,
+            None => return false,
+        };
+        if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) && area.flags.no_execute {
+            return false;
+        }
+
+        let write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+        let mut mapper = unsafe { self.mapper() };
+        match self.populate(&mut mapper, &mut state, addr, write) {
+            Ok(changed) => changed,
+            Err(_) => false,
+        }
+    }
+
+    /*****************************************************
+    * Writes up to `count` pages to swap and frees their
+    	frames, returns how many went. Pages are picked
+    	like a clock: one that was used since the hand last
+    	passed it gets another round. Shared frames stay.
+    *****************************************************/
+    pub fn swap_out(&self, count: usize) -> Result<usize, SwapError> {
+        let mut state = self.state.lock();
+        self.reclaim(&mut state, count)
+    }
+
+    fn reclaim(&self, state: &mut State, count: usize) -> Result<usize, SwapError> {
+        if !swap::is_on() {
+            return Err(SwapError::NotOn);
+        }
+
+        // from the hand to the end, then twice around so cleared pages come up again
+        let areas: Vec<Area> = state.areas.values().copied().collect();
+        let pages = || areas.iter().flat_map(|area| area.pages());
+        let hand = state.hand;
+        let order = pages().skip_while(|page| page.start_address() < hand).chain(pages()).chain(pages());
+
+        let mut swapped = 0;
+        for page in order {
+            if swapped == count {
+                break;
+            }
+
+            let entry = match unsafe { leaf_entry(self.level_4_frame, page.start_address()) } {
+                Some(entry) => entry,
+                None => continue,
+            };
+            let frame = match entry.frame() {
+                Ok(frame) if !is_shared(frame) => frame,
+                _ => continue,
+            };
+
+            if entry.flags().contains(PageTableFlags::ACCESSED) {
+                entry.set_flags(entry.flags() - PageTableFlags::ACCESSED);
+                self.flush_page(page);
+                continue;
+            }
+
+            let owner = Owner { level_4_frame: self.level_4_frame, page: page.start_address() };
+            let slot = match swap::write_out(frame, owner) {
+                Ok(slot) => slot,
+                Err(error) if swapped == 0 => return Err(error),
+                Err(_) => break,
+            };
+            swap::set_swapped(entry, slot);
+            self.flush_page(page);
+            unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+
+            swapped += 1;
+            state.hand = page.start_address() + PAGE_SIZE;
+        }
+
+        Ok(swapped)
+    }
+
+    // a frame for a user page, swapping some out first if there is none left
+    fn allocate_frame(&self, state: &mut State) -> Option<PhysFrame> {
+        GlobalFrameAllocator.allocate_frame().or_else(|| {
+            self.reclaim(state, RECLAIM_PAGES).ok()?;
+            GlobalFrameAllocator.allocate_frame()
+        })
+    }
+
+    pub fn translate(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        let _state = self.state.lock();
+        unsafe { self.mapper() }.translate_addr(addr)
+    }
+
+    /*****************************************************
+    * Copies between user memory and the kernel through
+    	the physical memory map, so it works whether this
+    	address space is active or not. Pages are backed
+    	and copied on write just like for an access.
+    *****************************************************/
+    pub fn copy_to(&self, addr: VirtAddr, data: &[u8]) -> Result<(), MapError> {
+        self.for_each_chunk(addr, data.len(), true, |virt, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(data[offset..].as_ptr(), virt, len);
+        })
+    }
+
+    pub fn copy_from(&self, addr: VirtAddr, buffer: &mut [u8]) -> Result<(), MapError> {
+        self.for_each_chunk(addr, buffer.len(), false, |virt, offset, len| unsafe {
+            core::ptr::copy_nonoverlapping(virt, buffer[offset..].as_mut_ptr(), len);
+        })
+    }
+
+    // calls `f` with the kernel address of every piece of [addr, addr + len) that is in one page
+    fn for_each_chunk<F>(&self, addr: VirtAddr, len: usize, write: bool, mut f: F) -> Result<(), MapError>
+    where
+        F: FnMut(*mut u8, usize, usize),
+    {
+        check_user_range(addr, len)?;
+
+        let mut state = self.state.lock();
+        let mut mapper = unsafe { self.mapper() };
+        let mut offset = 0;
+        while offset < len {
+            let virt = addr + offset;
+            self.populate(&mut mapper, &mut state, virt, write)?;
+            let phys = mapper.translate_addr(virt).ok_or(MapError::NotMapped(virt))?;
+            let chunk = ((PAGE_SIZE - virt.as_u64() % PAGE_SIZE) as usize).min(len - offset);
+
+            f((physical_memory_offset() + phys.as_u64()).as_mut_ptr(), offset, chunk);
+            offset += chunk;
+        }
+
+        Ok(())
+    }
+
+    // the TLB only has entries of the active address space
+    fn flush(&self, flush: MapperFlush<Size4KiB>) {
+        if self.is_active() {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+    }
+
+    fn flush_page(&self, page: Page) {
+        if self.is_active() {
+            tlb::flush(page.start_address());
+        }
+    }
+
+    unsafe fn mapper(&self) -> OffsetPageTable<'static> {
+        OffsetPageTable::new(table(self.level_4_frame), physical_memory_offset())
+    }
+
+    fn sync_kernel_entries(&self) {
+        let kernel = unsafe { table(kernel_level_4_frame()) };
+        let own = unsafe { table(self.level_4_frame) };
+
+        for index in (0..USER_ENTRIES_START).chain(USER_ENTRIES_END..512) {
+            own[index] = kernel[index].clone();
+        }
+    }
+}
+
+/*********************************************************
+* Gives back every frame of user space and the tables
+	above them. The space can't be active anymore, CURRENT
+	would still have a reference to it.
+*********************************************************/
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let level_4 = unsafe { table(self.level_4_frame) };
+        for entry in level_4.iter_mut().take(USER_ENTRIES_END).skip(USER_ENTRIES_START) {
+            if let Ok(frame) = entry.frame() {
+                unsafe { free_table(frame, 3) };
+            }
+            entry.set_unused();
+        }
+
+        unsafe { free_table_frame(self.level_4_frame) };
+    }
+}
+
+// frees everything `frame` maps and then the table itself
+unsafe fn free_table(frame: PhysFrame, level: u8) {
+    for entry in table(frame).iter() {
+        // user space has no huge pages, `frame()` skips them anyway
+        if let Ok(child) = entry.frame() {
+            if level > 1 {
+                free_table(child, level - 1);
+            } else {
+                release_frame(child);
+            }
+        } else if let Some(slot) = swap::slot(entry).filter(|_| level == 1) {
+            swap::free_slot(slot);
+        }
+    }
+
+    free_table_frame(frame);
+}
+
+// a zeroed table from PAGE_TABLES, it is only known by its frame after this
+fn allocate_table() -> Option<PhysFrame> {
+    let table = PAGE_TABLES.alloc(PageTable::new())?;
+    let frame = PhysFrame::containing_address(CacheBox::phys_addr(&table));
+    CacheBox::into_raw(table);
+    Some(frame)
+}
+
+unsafe fn free_table_frame(frame: PhysFrame) {
+    drop(PAGE_TABLES.from_raw(table(frame)));
+}
+
+/*********************************************************
+* Gives the mapper its intermediate tables from
+	PAGE_TABLES, so they are freed the same way
+*********************************************************/
+struct TableAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for TableAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        allocate_table()
+    }
+}
+
+/*********************************************************
+* Reads the page in `slot` back for swapoff, into the
+	address space it was swapped out of. Entries that
+	don't point at the slot anymore are left alone.
+	Nothing else may change the address space meanwhile.
+*********************************************************/
+pub(super) fn restore_swapped(slot: u64, owner: Owner) -> Result<(), SwapError> {
+    let entry = match unsafe { leaf_entry(owner.level_4_frame, owner.page) } {
+        Some(entry) if swap::slot(entry) == Some(slot) => entry,
+        _ => {
+            swap::free_slot(slot);
+            return Ok(());
+        }
+    };
+
+    let frame = GlobalFrameAllocator.allocate_frame().ok_or(SwapError::OutOfFrames)?;
+    if let Err(error) = swap::read_in(slot, frame) {
+        unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+        return Err(error);
+    }
+    // entries that weren't present are never in the TLB
+    swap::set_restored(entry, frame);
+    Ok(())
+}
+
+// the level 1 entry for `addr` in the tables under `level_4_frame`, if they go down that far
+unsafe fn leaf_entry(level_4_frame: PhysFrame, addr: VirtAddr) -> Option<&'static mut PageTableEntry> {
+    let indexes = [addr.p4_index(), addr.p3_index(), addr.p2_index()];
+    let mut current = table(level_4_frame);
+    for index in indexes.iter() {
+        // `frame()` fails for huge pages too
+        current = table(current[*index].frame().ok()?);
+    }
+    Some(&mut current[addr.p1_index()])
+}
+
+fn share_frame(frame: PhysFrame) {
+    *SHARED_FRAMES.lock().entry(frame).or_insert(0) += 1;
+}
+
+// drops one extra reference, false if there was none
+fn unshare_frame(frame: PhysFrame) -> bool {
+    let mut shared = SHARED_FRAMES.lock();
+    match shared.get_mut(&frame) {
+        Some(count) if *count > 1 => *count -= 1,
+        Some(_) => {
+            shared.remove(&frame);
+        }
+        None => return false,
+    }
+    true
+}
+
+fn is_shared(frame: PhysFrame) -> bool {
+    SHARED_FRAMES.lock().contains_key(&frame)
+}
+
+// frees `frame` unless it is mapped somewhere else too
+fn release_frame(frame: PhysFrame) {
+    if !unshare_frame(frame) {
+        unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+    }
+}
+
+unsafe fn copy_frame(from: PhysFrame, to: PhysFrame) {
+    let offset = physical_memory_offset();
+    core::ptr::copy_nonoverlapping(
+        (offset + from.start_address().as_u64()).as_ptr::<u8>(),
+        (offset + to.start_address().as_u64()).as_mut_ptr::<u8>(),
+        PAGE_SIZE as usize,
+    );
+}
+
+unsafe fn table(frame: PhysFrame) -> &'static mut PageTable {
+    let virt = physical_memory_offset() + frame.start_address().as_u64();
+    &mut *virt.as_mut_ptr::<PageTable>()
+}
+
+// the area of whole pages covering `size` bytes at `start`
+fn user_area(start: VirtAddr, size: usize, flags: MapFlags) -> Result<Area, MapError> {
+    check_user_range(start, size)?;
+    let first = Page::<Size4KiB>::containing_address(start).start_address();
+    let pages = mapping::page_range(start, size).count() as u64;
+
+    Ok(Area {
+        start: first,
+        end: first + pages * PAGE_SIZE,
+        flags: MapFlags { user: true, ..flags },
+    })
+}
+
+fn check_user_range(start: VirtAddr, size: usize) -> Result<(), MapError> {
+    let end = start.as_u64().checked_add(size as u64);
+    match end {
+        Some(end) if start.as_u64() >= USER_SPACE_START && end <= USER_SPACE_END => Ok(()),
+        _ => Err(MapError::OutsideUserSpace(start)),
+    }
+}
+
+/*********************************************************
+* Loads `space` into CR3, or the kernel's table for None.
+	The executor calls this before polling a task, CR3 is
+	only written if it actually changes.
+*********************************************************/
+pub fn switch_to(space: Option<&Arc<AddressSpace>>) {
+    let target = space.map(|space| space.level_4_frame).unwrap_or_else(kernel_level_4_frame);
+
+    let previous = {
+        let mut current = CURRENT.lock();
+        let (frame, flags) = Cr3::read();
+        if frame != target {
+            if let Some(space) = space {
+                // picks up kernel mappings made since the last switch
+                space.sync_kernel_entries();
+            }
+            unsafe { Cr3::write(target, flags) };
+        }
+        core::mem::replace(&mut *current, space.cloned())
+    };
+
+    // dropped out here in case it was the last reference
+    drop(previous);
+}
+
+pub fn current() -> Option<Arc<AddressSpace>> {
+    CURRENT.lock().clone()
+}
+
+/*************************************************************
+* Called on page faults. A kernel mapping in a level 4 entry
+	that was new since the active address space last synced
+	is copied over, true if the access can be retried.
+*************************************************************/
+pub(crate) fn sync_kernel_entry(addr: VirtAddr) -> bool {
+    let index = usize::from(addr.p4_index());
+    let active = Cr3::read().0;
+    let kernel = kernel_level_4_frame();
+    // before `memory::init` there is nothing to sync from
+    if kernel.start_address().is_null() || active == kernel || (USER_ENTRIES_START..USER_ENTRIES_END).contains(&index) {
+        return false;
+    }
+
+    let (kernel, active) = unsafe { (table(kernel), table(active)) };
+    if kernel[index].is_unused() || active[index].addr() == kernel[index].addr() {
+        return false;
+    }
+
+    active[index] = kernel[index].clone();
+    true
+}
+
+/*************************************************************
+* Called on page faults in user space. The active address
+	space backs the page or copies it if its areas allow the
+	access, true if the access can be retried.
+*************************************************************/
+pub(crate) fn handle_page_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    if addr.as_u64() < USER_SPACE_START || addr.as_u64() >= USER_SPACE_END {
+        return false;
+    }
+
+    let space = match CURRENT.try_lock() {
+        Some(current) => current.clone(),
+        None => return false,
+    };
+    space.map_or(false, |space| space.handle_fault(addr, error_code))
+}
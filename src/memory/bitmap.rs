@@ -0,0 +1,234 @@
+/**************************************************************************************************
+* Name : 								  memory/bitmap.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					   Bitmap Physical Frame Allocator
+* Version : 									 0.1
+**************************************************************************************************/
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use core::slice;
+use x86_64::{
+    PhysAddr,
+    VirtAddr,
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB, frame::PhysFrameRange},
+};
+
+const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = 64;
+
+/*******************************************************************
+* One bit per 4 KiB frame, set while the frame is in use. Frames
+	that aren't usable RAM stay set forever. The bitmap itself lives
+	in the first usable region big enough for it.
+*******************************************************************/
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    frame_count: usize,
+    next_word: usize,
+    // where the last run of frames in a row ended
+    next_run: usize,
+    total_frames: usize,
+    free_frames: usize,
+    memory_map: &'static MemoryMap,
+    // the frames the bitmap itself is in
+    bitmap_frames: core::ops::Range<usize>,
+}
+
+impl BitmapFrameAllocator {
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable_regions = || memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable);
+
+        let highest_address = usable_regions()
+            .map(|r| r.range.end_addr())
+            .max()
+            .expect("no usable memory");
+
+        let frame_count = (highest_address / FRAME_SIZE) as usize;
+        let word_count = (frame_count + BITS_PER_WORD - 1) / BITS_PER_WORD;
+        let bitmap_size = (word_count * 8) as u64;
+
+        let bitmap_start = usable_regions()
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= bitmap_size)
+            .map(|r| r.range.start_addr())
+            .expect("no usable region fits the frame bitmap");
+
+        let bitmap_ptr = (physical_memory_offset + bitmap_start).as_mut_ptr::<u64>();
+        let bitmap = slice::from_raw_parts_mut(bitmap_ptr, word_count);
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            frame_count,
+            next_word: 0,
+            next_run: 0,
+            total_frames: 0,
+            free_frames: 0,
+            memory_map,
+            bitmap_frames: 0..0,
+        };
+
+        for region in usable_regions() {
+            let start = (region.range.start_addr() / FRAME_SIZE) as usize;
+            let end = (region.range.end_addr() / FRAME_SIZE) as usize;
+            for index in start..end {
+                allocator.clear(index);
+            }
+            allocator.total_frames += end - start;
+            allocator.free_frames += end - start;
+        }
+
+        /*************************************
+        * the bitmap can't hand out itself
+        *************************************/
+        let bitmap_first = (bitmap_start / FRAME_SIZE) as usize;
+        let bitmap_frames = ((bitmap_size + FRAME_SIZE - 1) / FRAME_SIZE) as usize;
+        for index in bitmap_first..bitmap_first + bitmap_frames {
+            allocator.set(index);
+        }
+        allocator.free_frames -= bitmap_frames;
+        allocator.bitmap_frames = bitmap_first..bitmap_first + bitmap_frames;
+
+        allocator
+    }
+
+    pub fn memory_map(&self) -> &'static MemoryMap {
+        self.memory_map
+    }
+
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_frames
+    }
+
+    pub fn used_frames(&self) -> usize {
+        self.total_frames - self.free_frames
+    }
+
+    /*********************************************************************
+    * Finds `count` free frames in a row, starting at a multiple of
+    	`align` frames and ending below `limit` if there is one. Needed for
+    	huge pages and for devices that can't deal with scattered memory.
+    	Like single frames, the search starts after the last run.
+    *********************************************************************/
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize, limit: Option<PhysAddr>)
+        -> Option<PhysFrameRange>
+    {
+        if count == 0 || align == 0 {
+            return None;
+        }
+
+        let frame_limit = match limit {
+            Some(limit) => ((limit.as_u64() / FRAME_SIZE) as usize).min(self.frame_count),
+            None => self.frame_count,
+        };
+
+        let hint = (self.next_run + align - 1) / align * align;
+        let start = self.find_run(hint, frame_limit, count, align)
+            .or_else(|| if hint > 0 { self.find_run(0, frame_limit, count, align) } else { None })?;
+
+        for index in start..start + count {
+            self.set(index);
+        }
+        self.free_frames -= count;
+        self.next_run = start + count;
+
+        Some(PhysFrame::range(Self::frame(start), Self::frame(start + count)))
+    }
+
+    // the first aligned run of `count` free frames from `start` on that ends below `end`
+    fn find_run(&self, mut start: usize, end: usize, count: usize, align: usize) -> Option<usize> {
+        while start + count <= end {
+            match (start..start + count).rev().find(|&index| self.is_set(index)) {
+                // skip past the used frame, keeping the alignment
+                Some(used) => start = (used + 1 + align - 1) / align * align,
+                None => return Some(start),
+            }
+        }
+
+        None
+    }
+
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        for frame in range {
+            self.deallocate_frame(frame);
+        }
+    }
+
+    /*********************************************
+    * Usable RAM the allocator hands out. Reserved
+    	frames are set in the bitmap too, they must
+    	never be freed into it.
+    *********************************************/
+    fn is_usable(&self, index: usize) -> bool {
+        let address = index as u64 * FRAME_SIZE;
+        !self.bitmap_frames.contains(&index)
+            && self.memory_map.iter().any(|region| {
+                region.region_type == MemoryRegionType::Usable
+                    && (region.range.start_addr()..region.range.end_addr()).contains(&address)
+            })
+    }
+
+    fn frame(index: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+    }
+
+    fn is_set(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] |= 1 << (index % BITS_PER_WORD);
+    }
+
+    fn clear(&mut self, index: usize) {
+        self.bitmap[index / BITS_PER_WORD] &= !(1 << (index % BITS_PER_WORD));
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let word_count = self.bitmap.len();
+
+        /**************************************************
+        * start where the last allocation left off, full
+        	words are skipped 64 frames at a time
+        **************************************************/
+        for offset in 0..word_count {
+            let word_index = (self.next_word + offset) % word_count;
+            let word = self.bitmap[word_index];
+            if word == u64::MAX {
+                continue;
+            }
+
+            let index = word_index * BITS_PER_WORD + word.trailing_ones() as usize;
+            if index >= self.frame_count {
+                continue;
+            }
+
+            self.set(index);
+            self.free_frames -= 1;
+            self.next_word = word_index;
+            return Some(Self::frame(index));
+        }
+
+        None
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        assert!(index < self.frame_count, "freed frame {:?} is outside of RAM", frame);
+        assert!(self.is_usable(index), "freed frame {:?} isn't usable RAM", frame);
+        assert!(self.is_set(index), "frame {:?} freed twice", frame);
+
+        self.clear(index);
+        self.free_frames += 1;
+    }
+}
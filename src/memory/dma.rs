@@ -0,0 +1,201 @@
+/**************************************************************************************************
+* Name : 								    memory/dma.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			    Physically Contiguous Buffers for Device DMA
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{
+    allocate_contiguous_frames, deallocate_contiguous_frames, kernel_mapper, physical_memory_offset,
+    mapping::{self, BoundsError, MapError, MapFlags},
+};
+use core::{arch::{asm, x86_64::__cpuid}, mem};
+use x86_64::{
+    instructions::tlb,
+    registers::model_specific::Msr,
+    structures::paging::{frame::PhysFrameRange, Mapper, Page, PageTableFlags, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+const FOUR_GIB: u64 = 0x_1_0000_0000;
+
+/*********************************************************
+* Entry 4 of the PAT is picked by the PAT bit of a 4 KiB
+	entry with PWT and PCD clear. No MapFlags value sets
+	that bit, so the entry is free to be made write-
+	combining the first time a buffer asks for that.
+	Entries 0-3 keep their defaults.
+*********************************************************/
+const IA32_PAT: u32 = 0x277;
+const PAT_WRITE_COMBINING: u64 = 0x01;
+const PAT_WRITE_COMBINING_ENTRY: u64 = 4;
+
+// the PAT bit of a 4 KiB entry sits where higher levels keep the huge page bit
+const PAT_4KIB: PageTableFlags = PageTableFlags::HUGE_PAGE;
+
+static PAT_READY: spin::Once<bool> = spin::Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    Uncached,
+    // writes are buffered and sent in bursts, reads aren't cached
+    WriteCombining,
+}
+
+/*********************************************************
+* Frames in a row with a known physical address for a
+	device to read and write, mapped into the driver
+	window without caching. Everything is freed on drop.
+
+	The physical memory map still has a write-back alias
+	of the frames. Only the buffer's own mapping may be
+	used while it's alive, the alias is only written to
+	zero the frames before they get mapped uncached.
+*********************************************************/
+pub struct DmaBuffer {
+    virt: VirtAddr,
+    frames: PhysFrameRange,
+    size: usize,
+    cache: CacheMode,
+}
+
+impl DmaBuffer {
+    pub fn new(size: usize, cache: CacheMode) -> Result<Self, MapError> {
+        Self::allocate(size, cache, None)
+    }
+
+    // for devices that only take 32 bit addresses
+    pub fn new_below_4gib(size: usize, cache: CacheMode) -> Result<Self, MapError> {
+        Self::allocate(size, cache, Some(PhysAddr::new(FOUR_GIB)))
+    }
+
+    fn allocate(size: usize, cache: CacheMode, limit: Option<PhysAddr>) -> Result<Self, MapError> {
+        let pages = ((size as u64 + PAGE_SIZE - 1) / PAGE_SIZE).max(1) as usize;
+        let frames = allocate_contiguous_frames(pages, 1, limit).ok_or(MapError::OutOfFrames)?;
+        let phys = frames.start.start_address();
+
+        /*****************************************************
+        * zeroed through the physical memory map, which
+        	caches, so that is written back and dropped from
+        	the cache before the device or the uncached
+        	mapping get to see the memory
+        *****************************************************/
+        unsafe {
+            core::ptr::write_bytes((physical_memory_offset() + phys.as_u64()).as_mut_ptr::<u8>(), 0, pages * PAGE_SIZE as usize);
+            asm!("wbinvd", options(nostack));
+        }
+
+        // without a PAT there is nothing better than uncached
+        let cache = match cache {
+            CacheMode::WriteCombining if write_combining_available() => CacheMode::WriteCombining,
+            _ => CacheMode::Uncached,
+        };
+        let flags = match cache {
+            CacheMode::Uncached => MapFlags::MMIO,
+            CacheMode::WriteCombining => MapFlags::KERNEL_DATA,
+        };
+
+        let mapped = mapping::reserve_window(pages * PAGE_SIZE as usize)
+            .and_then(|virt| unsafe { mapping::map_range_to(virt, phys, pages * PAGE_SIZE as usize, flags) }.map(|_| virt));
+        match mapped {
+            Ok(virt) => {
+                if cache == CacheMode::WriteCombining {
+                    unsafe { use_write_combining(virt, pages) };
+                }
+                Ok(DmaBuffer { virt, frames, size, cache })
+            }
+            Err(error) => {
+                unsafe { deallocate_contiguous_frames(frames) };
+                Err(error)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.virt
+    }
+
+    // where the device finds the buffer, the rest follows right after
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.frames.start.start_address()
+    }
+
+    pub fn cache_mode(&self) -> CacheMode {
+        self.cache
+    }
+
+    pub fn read<T: Copy>(&self, offset: usize) -> Result<T, BoundsError> {
+        mapping::check_bounds(offset, mem::size_of::<T>(), self.size)?;
+        Ok(unsafe { (self.virt + offset).as_ptr::<T>().read_volatile() })
+    }
+
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) -> Result<(), BoundsError> {
+        mapping::check_bounds(offset, mem::size_of::<T>(), self.size)?;
+        unsafe { (self.virt + offset).as_mut_ptr::<T>().write_volatile(value) };
+        Ok(())
+    }
+
+    pub fn read_bytes(&self, offset: usize, buffer: &mut [u8]) -> Result<(), BoundsError> {
+        mapping::check_bounds(offset, buffer.len(), self.size)?;
+        for (index, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { (self.virt + offset + index).as_ptr::<u8>().read_volatile() };
+        }
+        Ok(())
+    }
+
+    pub fn write_bytes(&mut self, offset: usize, data: &[u8]) -> Result<(), BoundsError> {
+        mapping::check_bounds(offset, data.len(), self.size)?;
+        for (index, &byte) in data.iter().enumerate() {
+            unsafe { (self.virt + offset + index).as_mut_ptr::<u8>().write_volatile(byte) };
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        let pages = self.frames.end - self.frames.start;
+        let _ = mapping::unmap_range(self.virt, (pages * PAGE_SIZE) as usize, false);
+        unsafe { deallocate_contiguous_frames(self.frames) };
+    }
+}
+
+fn write_combining_available() -> bool {
+    *PAT_READY.call_once(|| {
+        // CPUID 1 EDX bit 16
+        if unsafe { __cpuid(1).edx } & (1 << 16) == 0 {
+            return false;
+        }
+
+        let shift = PAT_WRITE_COMBINING_ENTRY * 8;
+        unsafe {
+            let mut pat = Msr::new(IA32_PAT);
+            let entries = pat.read() & !(0xff << shift);
+            pat.write(entries | PAT_WRITE_COMBINING << shift);
+            asm!("wbinvd", options(nostack));
+        }
+        tlb::flush_all();
+        true
+    })
+}
+
+// points the freshly mapped write-back pages at the write-combining PAT entry
+unsafe fn use_write_combining(virt: VirtAddr, pages: usize) {
+    let mut mapper = kernel_mapper();
+    let flags = MapFlags::KERNEL_DATA.page_table_flags() | PAT_4KIB;
+
+    for index in 0..pages as u64 {
+        let page = Page::<Size4KiB>::containing_address(virt + index * PAGE_SIZE);
+        mapper.update_flags(page, flags).expect("DMA page was just mapped").flush();
+    }
+}
@@ -0,0 +1,473 @@
+/**************************************************************************************************
+* Name : 								  memory/mapping.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				 Mapping and Unmapping Virtual Memory Ranges
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{protection, kernel_mapper, deallocate_contiguous_frames, physical_memory_offset, GlobalFrameAllocator};
+use core::{arch::x86_64::__cpuid, fmt, mem, sync::atomic::{AtomicU64, Ordering}};
+use x86_64::{
+    structures::paging::{
+        mapper::{MapToError, MappedFrame, TranslateResult, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags, PhysFrame,
+        Size1GiB, Size2MiB, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+/*********************************************************
+* Virtual addresses handed out to MMIO regions and page
+	buffers. They are never reused, the window is huge.
+*********************************************************/
+pub const DRIVER_WINDOW_START: u64 = 0x_6666_0000_0000;
+pub const DRIVER_WINDOW_SIZE: u64 = 0x_0100_0000_0000; // 1 TiB
+
+static NEXT_WINDOW_ADDR: AtomicU64 = AtomicU64::new(DRIVER_WINDOW_START);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MapFlags {
+    pub writable: bool,
+    pub no_execute: bool,
+    pub user: bool,
+    pub cache_disable: bool,
+    pub write_through: bool,
+}
+
+impl MapFlags {
+    pub const KERNEL_CODE: MapFlags = MapFlags {
+        writable: false,
+        no_execute: false,
+        user: false,
+        cache_disable: false,
+        write_through: false,
+    };
+
+    pub const KERNEL_DATA: MapFlags = MapFlags {
+        writable: true,
+        no_execute: true,
+        ..Self::KERNEL_CODE
+    };
+
+    pub const MMIO: MapFlags = MapFlags {
+        cache_disable: true,
+        write_through: true,
+        ..Self::KERNEL_DATA
+    };
+
+    pub fn page_table_flags(&self) -> PageTableFlags {
+        let mut flags = PageTableFlags::PRESENT;
+        flags.set(PageTableFlags::WRITABLE, self.writable);
+        // NX is a reserved bit until EFER.NXE is set
+        flags.set(PageTableFlags::NO_EXECUTE, self.no_execute && protection::no_execute_enabled());
+        flags.set(PageTableFlags::USER_ACCESSIBLE, self.user);
+        flags.set(PageTableFlags::NO_CACHE, self.cache_disable);
+        flags.set(PageTableFlags::WRITE_THROUGH, self.write_through);
+        flags
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    AlreadyMapped(VirtAddr),
+    NotMapped(VirtAddr),
+    HugePage(VirtAddr),
+    OutsideUserSpace(VirtAddr),
+    NotWritable(VirtAddr),
+    OutOfFrames,
+    OutOfAddressSpace,
+    Unaligned,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::AlreadyMapped(addr) => write!(f, "{:#x} is already mapped", addr.as_u64()),
+            MapError::NotMapped(addr) => write!(f, "{:#x} is not mapped", addr.as_u64()),
+            MapError::HugePage(addr) => write!(f, "{:#x} is inside a huge page", addr.as_u64()),
+            MapError::OutsideUserSpace(addr) => write!(f, "{:#x} is outside of user space", addr.as_u64()),
+            MapError::NotWritable(addr) => write!(f, "{:#x} is not writable", addr.as_u64()),
+            MapError::OutOfFrames => write!(f, "out of physical memory"),
+            MapError::OutOfAddressSpace => write!(f, "out of virtual address space"),
+            MapError::Unaligned => write!(f, "virtual and physical address aren't equally aligned"),
+        }
+    }
+}
+
+pub(super) fn map_error<S: PageSize>(error: MapToError<S>, addr: VirtAddr) -> MapError {
+    match error {
+        MapToError::FrameAllocationFailed => MapError::OutOfFrames,
+        MapToError::ParentEntryHugePage => MapError::HugePage(addr),
+        MapToError::PageAlreadyMapped(_) => MapError::AlreadyMapped(addr),
+    }
+}
+
+fn unmap_error(error: UnmapError, addr: VirtAddr) -> MapError {
+    match error {
+        UnmapError::ParentEntryHugePage => MapError::HugePage(addr),
+        UnmapError::PageNotMapped | UnmapError::InvalidFrameAddress(_) => MapError::NotMapped(addr),
+    }
+}
+
+// every page touching [start, start + size)
+pub(super) fn page_range(start: VirtAddr, size: usize) -> impl Iterator<Item = Page> {
+    let first = Page::<Size4KiB>::containing_address(start);
+    let count = (start.as_u64() - first.start_address().as_u64() + size as u64 + PAGE_SIZE - 1) / PAGE_SIZE;
+    (0..count).map(move |index| first + index)
+}
+
+/*********************************************************
+* Maps the pages covering `size` bytes at `start` to fresh
+	zeroed frames. Nothing stays mapped if it fails halfway.
+*********************************************************/
+pub fn map_range(start: VirtAddr, size: usize, flags: MapFlags) -> Result<(), MapError> {
+    let mut mapper = unsafe { kernel_mapper() };
+    let mut frame_allocator = GlobalFrameAllocator;
+
+    for (mapped, page) in page_range(start, size).enumerate() {
+        let result = match frame_allocator.allocate_frame() {
+            Some(frame) => {
+                zero_frame(frame);
+                unsafe { mapper.map_to(page, frame, flags.page_table_flags(), &mut frame_allocator) }
+                    .map(|flush| flush.flush())
+                    .map_err(|error| {
+                        unsafe { frame_allocator.deallocate_frame(frame) };
+                        map_error(error, page.start_address())
+                    })
+            }
+            None => Err(MapError::OutOfFrames),
+        };
+
+        if let Err(error) = result {
+            unmap_pages(start, mapped, true);
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/*********************************************************
+* Maps `size` bytes at `start` to the physical memory at
+	`phys`, which nobody else should be using as RAM.
+	Both need the same offset into their page.
+*********************************************************/
+pub unsafe fn map_range_to(start: VirtAddr, phys: PhysAddr, size: usize, flags: MapFlags) -> Result<(), MapError> {
+    if start.as_u64() % PAGE_SIZE != phys.as_u64() % PAGE_SIZE {
+        return Err(MapError::Unaligned);
+    }
+
+    let mut mapper = kernel_mapper();
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(phys);
+
+    for (mapped, page) in page_range(start, size).enumerate() {
+        let frame = first_frame + mapped as u64;
+        let result = mapper
+            .map_to(page, frame, flags.page_table_flags(), &mut GlobalFrameAllocator)
+            .map(|flush| flush.flush())
+            .map_err(|error| map_error(error, page.start_address()));
+
+        if let Err(error) = result {
+            unmap_pages(start, mapped, false);
+            return Err(error);
+        }
+    }
+
+    Ok(())
+}
+
+/*********************************************************
+* Maps `size` bytes at `start` to `phys` with the biggest
+	pages the alignment of both allows, for large regions
+	like framebuffers. 1 GiB pages are only used if the
+	CPU has them.
+*********************************************************/
+pub unsafe fn map_large_range_to(start: VirtAddr, phys: PhysAddr, size: usize, flags: MapFlags) -> Result<(), MapError> {
+    if start.as_u64() % PAGE_SIZE != phys.as_u64() % PAGE_SIZE {
+        return Err(MapError::Unaligned);
+    }
+
+    let mut mapper = kernel_mapper();
+    let first_page = Page::<Size4KiB>::containing_address(start).start_address();
+    let first_frame = phys.align_down(PAGE_SIZE);
+    let total = page_range(start, size).count() as u64 * PAGE_SIZE;
+    let gib_pages = has_1gib_pages();
+
+    let mut offset = 0;
+    while offset < total {
+        let virt = first_page + offset;
+        let phys = first_frame + offset;
+        let fits = |page_size: u64| {
+            virt.is_aligned(page_size) && phys.is_aligned(page_size) && total - offset >= page_size
+        };
+
+        let result = if gib_pages && fits(Size1GiB::SIZE) {
+            map_page_to::<Size1GiB>(&mut mapper, virt, phys, flags)
+        } else if fits(Size2MiB::SIZE) {
+            map_page_to::<Size2MiB>(&mut mapper, virt, phys, flags)
+        } else {
+            map_page_to::<Size4KiB>(&mut mapper, virt, phys, flags)
+        };
+
+        match result {
+            Ok(page_size) => offset += page_size,
+            Err(error) => {
+                let _ = unmap_range(first_page, offset as usize, false);
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+unsafe fn map_page_to<S: PageSize>(mapper: &mut OffsetPageTable<'static>, virt: VirtAddr, phys: PhysAddr, flags: MapFlags)
+    -> Result<u64, MapError>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    let page = Page::<S>::containing_address(virt);
+    let frame = PhysFrame::<S>::containing_address(phys);
+
+    mapper
+        .map_to(page, frame, flags.page_table_flags(), &mut GlobalFrameAllocator)
+        .map(|flush| flush.flush())
+        .map_err(|error| map_error(error, virt))?;
+
+    Ok(S::SIZE)
+}
+
+// CPUID 0x8000_0001 EDX bit 26
+fn has_1gib_pages() -> bool {
+    unsafe {
+        __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 26) != 0
+    }
+}
+
+/*********************************************************
+* Unmaps the pages covering `size` bytes at `start`, and
+	gives their frames back if `free_frames` is set. Huge
+	pages are unmapped whole, they can't be split. Pages
+	that weren't mapped are skipped, the first problem is
+	reported once the rest are unmapped.
+*********************************************************/
+pub fn unmap_range(start: VirtAddr, size: usize, free_frames: bool) -> Result<(), MapError> {
+    let mut mapper = unsafe { kernel_mapper() };
+    let first_page = Page::<Size4KiB>::containing_address(start).start_address();
+    let end = first_page + page_range(start, size).count() as u64 * PAGE_SIZE;
+    let mut first_error = None;
+
+    let mut addr = first_page;
+    while addr < end {
+        let (result, page_size) = match mapper.translate(addr) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(_), .. } => {
+                (unmap_page::<Size4KiB>(&mut mapper, addr, end, free_frames), Size4KiB::SIZE)
+            }
+            TranslateResult::Mapped { frame: MappedFrame::Size2MiB(_), .. } => {
+                (unmap_page::<Size2MiB>(&mut mapper, addr, end, free_frames), Size2MiB::SIZE)
+            }
+            TranslateResult::Mapped { frame: MappedFrame::Size1GiB(_), .. } => {
+                (unmap_page::<Size1GiB>(&mut mapper, addr, end, free_frames), Size1GiB::SIZE)
+            }
+            TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
+                (Err(MapError::NotMapped(addr)), Size4KiB::SIZE)
+            }
+        };
+
+        if let Err(error) = result {
+            first_error.get_or_insert(error);
+        }
+        // continue after the page `addr` is in, whatever its size
+        addr = addr.align_down(page_size) + page_size;
+    }
+
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+fn unmap_page<S: PageSize>(mapper: &mut OffsetPageTable<'static>, addr: VirtAddr, end: VirtAddr, free_frames: bool)
+    -> Result<(), MapError>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    // only part of the huge page is in the range
+    if !addr.is_aligned(S::SIZE) || end - addr < S::SIZE {
+        return Err(MapError::HugePage(addr));
+    }
+
+    let page = Page::<S>::containing_address(addr);
+    let (frame, flush) = mapper.unmap(page).map_err(|error| unmap_error(error, addr))?;
+    flush.flush();
+
+    if free_frames {
+        let first = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+        unsafe { deallocate_contiguous_frames(PhysFrame::range(first, first + S::SIZE / PAGE_SIZE)) };
+    }
+    Ok(())
+}
+
+fn unmap_pages(start: VirtAddr, count: usize, free_frames: bool) {
+    if count > 0 {
+        // these were mapped by us a moment ago
+        let first = Page::<Size4KiB>::containing_address(start);
+        let _ = unmap_range(first.start_address(), count * PAGE_SIZE as usize, free_frames);
+    }
+}
+
+pub(super) fn zero_frame(frame: PhysFrame) {
+    let virt = physical_memory_offset() + frame.start_address().as_u64();
+    unsafe { core::ptr::write_bytes(virt.as_mut_ptr::<u8>(), 0, PAGE_SIZE as usize) };
+}
+
+/*********************************************************
+* Takes `size` bytes (whole pages, plus a guard page) of
+	the driver window
+*********************************************************/
+pub(super) fn reserve_window(size: usize) -> Result<VirtAddr, MapError> {
+    let pages = (size as u64 + PAGE_SIZE - 1) / PAGE_SIZE + 1;
+    let start = NEXT_WINDOW_ADDR.fetch_add(pages * PAGE_SIZE, Ordering::SeqCst);
+
+    if start + pages * PAGE_SIZE > DRIVER_WINDOW_START + DRIVER_WINDOW_SIZE {
+        return Err(MapError::OutOfAddressSpace);
+    }
+    Ok(VirtAddr::new(start))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoundsError {
+    pub offset: usize,
+    pub len: usize,
+    pub size: usize,
+}
+
+impl fmt::Display for BoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "access of {} bytes at offset {} is outside of {} bytes", self.len, self.offset, self.size)
+    }
+}
+
+pub(super) fn check_bounds(offset: usize, len: usize, size: usize) -> Result<(), BoundsError> {
+    match offset.checked_add(len) {
+        Some(end) if end <= size => Ok(()),
+        _ => Err(BoundsError { offset, len, size }),
+    }
+}
+
+/*********************************************************
+* Device registers mapped uncached into the driver window,
+	every access is volatile and checked against the size
+*********************************************************/
+pub struct MmioRegion {
+    virt: VirtAddr,
+    phys: PhysAddr,
+    size: usize,
+}
+
+impl MmioRegion {
+    pub unsafe fn map(phys: PhysAddr, size: usize) -> Result<Self, MapError> {
+        let page_offset = phys.as_u64() % PAGE_SIZE;
+        let virt = reserve_window(size + page_offset as usize)? + page_offset;
+        map_range_to(virt, phys, size, MapFlags::MMIO)?;
+
+        Ok(MmioRegion { virt, phys, size })
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.virt
+    }
+
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn read<T: Copy>(&self, offset: usize) -> Result<T, BoundsError> {
+        check_bounds(offset, mem::size_of::<T>(), self.size)?;
+        Ok(unsafe { (self.virt + offset).as_ptr::<T>().read_volatile() })
+    }
+
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) -> Result<(), BoundsError> {
+        check_bounds(offset, mem::size_of::<T>(), self.size)?;
+        unsafe { (self.virt + offset).as_mut_ptr::<T>().write_volatile(value) };
+        Ok(())
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        // the frames belong to the device
+        let _ = unmap_range(self.virt, self.size, false);
+    }
+}
+
+/*********************************************************
+* Zeroed pages for a driver to fill, with checked access
+	and the physical address of each page for the device
+*********************************************************/
+pub struct PageBuffer {
+    virt: VirtAddr,
+    pages: usize,
+}
+
+impl PageBuffer {
+    pub fn new(pages: usize) -> Result<Self, MapError> {
+        let virt = reserve_window(pages * PAGE_SIZE as usize)?;
+        map_range(virt, pages * PAGE_SIZE as usize, MapFlags::KERNEL_DATA)?;
+
+        Ok(PageBuffer { virt, pages })
+    }
+
+    pub fn len(&self) -> usize {
+        self.pages * PAGE_SIZE as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages == 0
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.virt
+    }
+
+    pub fn phys_addr(&self, offset: usize) -> Result<PhysAddr, BoundsError> {
+        check_bounds(offset, 1, self.len())?;
+        let mapper = unsafe { kernel_mapper() };
+        let page = Page::<Size4KiB>::containing_address(self.virt + offset);
+        let frame = mapper.translate_page(page).expect("page buffer is not mapped");
+
+        Ok(frame.start_address() + (offset as u64 % PAGE_SIZE))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.virt.as_ptr(), self.len()) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.virt.as_mut_ptr(), self.len()) }
+    }
+
+    pub fn read<T: Copy>(&self, offset: usize) -> Result<T, BoundsError> {
+        check_bounds(offset, mem::size_of::<T>(), self.len())?;
+        Ok(unsafe { (self.virt + offset).as_ptr::<T>().read_unaligned() })
+    }
+
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) -> Result<(), BoundsError> {
+        check_bounds(offset, mem::size_of::<T>(), self.len())?;
+        unsafe { (self.virt + offset).as_mut_ptr::<T>().write_unaligned(value) };
+        Ok(())
+    }
+}
+
+impl Drop for PageBuffer {
+    fn drop(&mut self) {
+        let _ = unmap_range(self.virt, self.len(), true);
+    }
+}
@@ -0,0 +1,258 @@
+/**************************************************************************************************
+* Name : 							    memory/protection.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 		    NX, Write Protection, SMEP and SMAP for Kernel Memory
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{kernel_mapper, memory_map, physical_memory_offset};
+use alloc::vec::Vec;
+use core::{arch::{asm, x86_64::{__cpuid, __cpuid_count}}, sync::atomic::{AtomicBool, Ordering}};
+use x86_64::{
+    instructions::tlb,
+    registers::{
+        control::{Cr0, Cr0Flags, Cr3, Cr4, Cr4Flags},
+        model_specific::{Efer, EferFlags},
+        rflags::{self, RFlags},
+    },
+    structures::paging::{
+        mapper::{MappedFrame, TranslateResult},
+        Mapper, Page, PageTable, PageTableFlags, Size4KiB, Translate,
+    },
+    VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/*********************************************************
+* Put there by the linker: where the kernel image starts,
+	where its code ends and where its data ends
+*********************************************************/
+extern "C" {
+    static __executable_start: u8;
+    static etext: u8;
+    static end: u8;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Protections {
+    pub no_execute: bool,
+    pub write_protect: bool,
+    pub smep: bool,
+    pub smap: bool,
+}
+
+/*********************************************************
+* A run of pages that is both writable and executable.
+	Every level of the walk has to allow it for that.
+*********************************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WritableExecutable {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub user: bool,
+}
+
+// what CPUID reports, write protection is always there in long mode
+pub fn supported() -> Protections {
+    let (extended, structured) = unsafe {
+        let extended = __cpuid(0x8000_0000).eax >= 0x8000_0001 && __cpuid(0x8000_0001).edx & (1 << 20) != 0;
+        let structured = if __cpuid(0).eax >= 7 { __cpuid_count(7, 0).ebx } else { 0 };
+        (extended, structured)
+    };
+
+    Protections {
+        no_execute: extended,
+        write_protect: true,
+        smep: structured & (1 << 7) != 0,
+        smap: structured & (1 << 20) != 0,
+    }
+}
+
+pub fn enabled() -> Protections {
+    let cr4 = Cr4::read();
+    Protections {
+        no_execute: Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE),
+        write_protect: Cr0::read().contains(Cr0Flags::WRITE_PROTECT),
+        smep: cr4.contains(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION),
+        smap: cr4.contains(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION),
+    }
+}
+
+/*******************************************************************
+* Turns on everything the CPU has, then makes the kernel image W^X
+	and the physical memory map non-executable. Needs `memory::init`
+	and the frame allocator. Returns what is enabled now.
+*******************************************************************/
+pub fn init() -> Protections {
+    let supported = supported();
+
+    unsafe {
+        if supported.no_execute {
+            Efer::update(|flags| *flags |= EferFlags::NO_EXECUTE_ENABLE);
+        }
+
+        // read-only pages are read-only for the kernel too
+        Cr0::update(|flags| *flags |= Cr0Flags::WRITE_PROTECT);
+
+        if supported.smep {
+            Cr4::update(|flags| *flags |= Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION);
+        }
+        if supported.smap {
+            Cr4::update(|flags| *flags |= Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION);
+        }
+    }
+    SMAP_ENABLED.store(supported.smap, Ordering::SeqCst);
+
+    protect_kernel_image();
+    if supported.no_execute {
+        protect_physical_memory_map();
+    }
+    tlb::flush_all();
+
+    enabled()
+}
+
+pub fn no_execute_enabled() -> bool {
+    Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE)
+}
+
+/*********************************************************
+* Runs `f` with user pages accessible to the kernel, for
+	code that reads or writes user memory on purpose.
+	Without SMAP the kernel can always access them.
+*********************************************************/
+pub fn with_user_access<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    // nested calls leave it to the outermost one to close access again
+    let open = SMAP_ENABLED.load(Ordering::Relaxed) && !rflags::read().contains(RFlags::ALIGNMENT_CHECK);
+    if open {
+        unsafe { asm!("stac", options(nostack)) };
+    }
+
+    let result = f();
+
+    if open {
+        unsafe { asm!("clac", options(nostack)) };
+    }
+    result
+}
+
+/*********************************************************
+* The code and read-only data in front of it lose their
+	write access, everything after the code can't be
+	executed anymore
+*********************************************************/
+fn protect_kernel_image() {
+    let (image_start, code_end, image_end) = unsafe {
+        (
+            VirtAddr::from_ptr(&__executable_start as *const u8),
+            VirtAddr::from_ptr(&etext as *const u8),
+            VirtAddr::from_ptr(&end as *const u8),
+        )
+    };
+    let no_execute = no_execute_enabled();
+    let mut mapper = unsafe { kernel_mapper() };
+
+    let first = Page::<Size4KiB>::containing_address(image_start);
+    let last = Page::<Size4KiB>::containing_address(image_end.align_up(PAGE_SIZE) - 1u64);
+    for page in Page::range_inclusive(first, last) {
+        let flags = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(_), flags, .. } => flags,
+            // not loaded, or in a huge page we can't change alone
+            _ => continue,
+        };
+
+        let wanted = if page.start_address() < code_end.align_up(PAGE_SIZE) {
+            flags - PageTableFlags::WRITABLE
+        } else if no_execute {
+            flags | PageTableFlags::NO_EXECUTE
+        } else {
+            flags
+        };
+
+        if wanted != flags {
+            if let Ok(flush) = unsafe { mapper.update_flags(page, wanted) } {
+                flush.ignore();
+            }
+        }
+    }
+}
+
+/*********************************************************
+* Sets NX on the level 4 entries of the physical memory
+	map, nothing below them is executed
+*********************************************************/
+fn protect_physical_memory_map() {
+    let physical_memory_end = match memory_map() {
+        Some(memory_map) => memory_map.iter().map(|region| region.range.end_addr()).max().unwrap_or(0),
+        None => return,
+    };
+    let code = VirtAddr::from_ptr(protect_physical_memory_map as *const u8);
+
+    let start = physical_memory_offset();
+    let last = start + physical_memory_end.max(1) - 1u64;
+    let level_4 = unsafe { kernel_table() };
+    for index in usize::from(start.p4_index())..=usize::from(last.p4_index()) {
+        // shouldn't ever be the case, but the kernel has to stay executable
+        if index == usize::from(code.p4_index()) {
+            continue;
+        }
+
+        let entry = &mut level_4[index];
+        if !entry.is_unused() {
+            entry.set_flags(entry.flags() | PageTableFlags::NO_EXECUTE);
+        }
+    }
+}
+
+unsafe fn kernel_table() -> &'static mut PageTable {
+    let frame = super::kernel_level_4_frame();
+    &mut *(physical_memory_offset() + frame.start_address().as_u64()).as_mut_ptr::<PageTable>()
+}
+
+/*********************************************************
+* Every writable and executable mapping in the active
+	page tables, neighbouring pages joined together
+*********************************************************/
+pub fn writable_executable_mappings() -> Vec<WritableExecutable> {
+    let mut found = Vec::new();
+    let level_4 = Cr3::read().0;
+    walk(level_4.start_address().as_u64(), 4, 0, PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE, &mut found);
+    found
+}
+
+// `allowed` is what every level above has allowed so far
+fn walk(table: u64, level: u8, base: u64, allowed: PageTableFlags, found: &mut Vec<WritableExecutable>) {
+    let table = unsafe { &*(physical_memory_offset() + table).as_ptr::<PageTable>() };
+    let shift = 12 + 9 * (level as u64 - 1);
+
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::NO_EXECUTE) {
+            continue;
+        }
+
+        let addr = base | (index as u64) << shift;
+        let allowed = allowed & flags;
+        if !allowed.contains(PageTableFlags::WRITABLE) {
+            continue;
+        }
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            let start = VirtAddr::new_truncate(addr);
+            let user = allowed.contains(PageTableFlags::USER_ACCESSIBLE);
+            match found.last_mut() {
+                Some(last) if last.start.as_u64() + last.size == start.as_u64() && last.user == user => last.size += 1 << shift,
+                _ => found.push(WritableExecutable { start, size: 1 << shift, user }),
+            }
+        } else {
+            walk(entry.addr().as_u64(), level - 1, addr, allowed, found);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+/**************************************************************************************************
+* Name : 								    memory/swap.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			    Paging Anonymous Memory out to a Block Device
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{address_space, physical_memory_offset};
+use crate::{block::{BlockDevice, BlockError, SECTOR_SIZE}, lock::Mutex};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use core::{fmt, slice};
+use x86_64::{
+    structures::paging::{page_table::PageTableEntry, PageTableFlags, PhysFrame},
+    PhysAddr, VirtAddr,
+};
+
+const PAGE_SIZE: u64 = 4096;
+const SECTORS_PER_SLOT: u64 = PAGE_SIZE / SECTOR_SIZE as u64;
+
+/*********************************************************
+* Written to the first sector by `format`. A device
+	without it is never swapped to, so a disk with
+	something else on it can't be overwritten by mistake.
+	The first page holds the header, slots come after it.
+*********************************************************/
+const SIGNATURE: &[u8; 8] = b"MIDASWAP";
+
+/*********************************************************
+* Set in a page table entry that isn't present because
+	its page is in swap. The address bits hold the slot,
+	the other flags are kept for when it comes back.
+*********************************************************/
+pub const SWAPPED: PageTableFlags = PageTableFlags::BIT_10;
+
+static SWAP: Mutex<Option<SwapArea>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapError {
+    AlreadyOn,
+    NotOn,
+    // the device doesn't have room for a single page
+    TooSmall,
+    // the device was never formatted for swap
+    NoSignature,
+    Full,
+    OutOfFrames,
+    Device(BlockError),
+}
+
+impl fmt::Display for SwapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SwapError::AlreadyOn => write!(f, "swap is already on"),
+            SwapError::NotOn => write!(f, "swap is off"),
+            SwapError::TooSmall => write!(f, "device is too small for swap"),
+            SwapError::NoSignature => write!(f, "device isn't formatted for swap"),
+            SwapError::Full => write!(f, "swap is full"),
+            SwapError::OutOfFrames => write!(f, "out of physical memory"),
+            SwapError::Device(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<BlockError> for SwapError {
+    fn from(error: BlockError) -> Self {
+        SwapError::Device(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapStats {
+    pub device: String,
+    pub slots: usize,
+    pub used: usize,
+}
+
+// whose page is in a slot, so swapoff can find its entry again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Owner {
+    pub level_4_frame: PhysFrame,
+    pub page: VirtAddr,
+}
+
+/*********************************************************
+* The device cut into page sized slots. Slots in `owners`
+	are in use, `closing` keeps new pages out while
+	swapoff brings the old ones back.
+*********************************************************/
+struct SwapArea {
+    device: Box<dyn BlockDevice>,
+    slots: u64,
+    owners: BTreeMap<u64, Owner>,
+    next: u64,
+    closing: bool,
+}
+
+impl SwapArea {
+    fn free_slot(&mut self) -> Option<u64> {
+        if self.closing || self.owners.len() as u64 == self.slots {
+            return None;
+        }
+
+        let slot = (self.next..self.slots)
+            .chain(0..self.next)
+            .find(|slot| !self.owners.contains_key(slot))?;
+        self.next = (slot + 1) % self.slots;
+        Some(slot)
+    }
+}
+
+/*********************************************************
+* Makes `device` usable for swap by writing the signature.
+	What was on it is lost once swap is on. Returns how
+	many pages fit.
+*********************************************************/
+pub fn format(device: &mut dyn BlockDevice) -> Result<usize, SwapError> {
+    let slots = slot_count(device)?;
+
+    let mut header = [0u8; SECTOR_SIZE];
+    header[..SIGNATURE.len()].copy_from_slice(SIGNATURE);
+    device.write(0, &header)?;
+    device.flush()?;
+    Ok(slots as usize)
+}
+
+/*********************************************************
+* Swaps to `device`, which `format` has to have been run
+	on. Only one device at a time. Returns how many pages
+	fit.
+*********************************************************/
+pub fn swapon(mut device: Box<dyn BlockDevice>) -> Result<usize, SwapError> {
+    let mut swap = SWAP.lock();
+    if swap.is_some() {
+        return Err(SwapError::AlreadyOn);
+    }
+
+    let slots = slot_count(&*device)?;
+    let mut header = [0u8; SECTOR_SIZE];
+    device.read(0, &mut header)?;
+    if &header[..SIGNATURE.len()] != SIGNATURE {
+        return Err(SwapError::NoSignature);
+    }
+
+    *swap = Some(SwapArea { device, slots, owners: BTreeMap::new(), next: 0, closing: false });
+    Ok(slots as usize)
+}
+
+// the page sized slots after the header
+fn slot_count(device: &dyn BlockDevice) -> Result<u64, SwapError> {
+    match (device.sector_count() / SECTORS_PER_SLOT).checked_sub(1) {
+        Some(slots) if slots > 0 => Ok(slots),
+        _ => Err(SwapError::TooSmall),
+    }
+}
+
+/*********************************************************
+* Brings every page in swap back into memory, then lets
+	go of the device. Swap stays on if there aren't enough
+	frames for all of them.
+*********************************************************/
+pub fn swapoff() -> Result<(), SwapError> {
+    let owners: Vec<(u64, Owner)> = {
+        let mut swap = SWAP.lock();
+        let area = swap.as_mut().ok_or(SwapError::NotOn)?;
+        area.closing = true;
+        area.owners.iter().map(|(slot, owner)| (*slot, *owner)).collect()
+    };
+
+    // the lock can't be held here, reading a page in takes it again
+    let mut result = Ok(());
+    for (slot, owner) in owners {
+        result = address_space::restore_swapped(slot, owner);
+        if result.is_err() {
+            break;
+        }
+    }
+
+    let mut swap = SWAP.lock();
+    match result {
+        Ok(()) => {
+            if let Some(mut area) = swap.take() {
+                area.device.flush()?;
+            }
+            Ok(())
+        }
+        Err(error) => {
+            if let Some(area) = swap.as_mut() {
+                area.closing = false;
+            }
+            Err(error)
+        }
+    }
+}
+
+pub fn is_on() -> bool {
+    SWAP.lock().is_some()
+}
+
+pub fn stats() -> Option<SwapStats> {
+    SWAP.lock().as_ref().map(|area| SwapStats {
+        device: String::from(area.device.name()),
+        slots: area.slots as usize,
+        used: area.owners.len(),
+    })
+}
+
+// writes the page in `frame` to a free slot and returns it
+pub(super) fn write_out(frame: PhysFrame, owner: Owner) -> Result<u64, SwapError> {
+    let mut swap = SWAP.lock();
+    let area = swap.as_mut().ok_or(SwapError::NotOn)?;
+    let slot = area.free_slot().ok_or(SwapError::Full)?;
+
+    area.device.write(first_sector(slot), unsafe { frame_bytes(frame) })?;
+    area.owners.insert(slot, owner);
+    Ok(slot)
+}
+
+// reads `slot` into `frame`, the slot is free again if that worked
+pub(super) fn read_in(slot: u64, frame: PhysFrame) -> Result<(), SwapError> {
+    let mut swap = SWAP.lock();
+    let area = swap.as_mut().ok_or(SwapError::NotOn)?;
+
+    area.device.read(first_sector(slot), unsafe { frame_bytes(frame) })?;
+    area.owners.remove(&slot);
+    Ok(())
+}
+
+// for pages in swap that are unmapped before they're read back
+pub(super) fn free_slot(slot: u64) {
+    if let Some(area) = SWAP.lock().as_mut() {
+        area.owners.remove(&slot);
+    }
+}
+
+// the slot `entry` points to, if its page is in swap
+pub(super) fn slot(entry: &PageTableEntry) -> Option<u64> {
+    let flags = entry.flags();
+    if flags.contains(PageTableFlags::PRESENT) || !flags.contains(SWAPPED) {
+        return None;
+    }
+    Some(entry.addr().as_u64() / PAGE_SIZE)
+}
+
+pub(super) fn set_swapped(entry: &mut PageTableEntry, slot: u64) {
+    let flags = entry.flags() - PageTableFlags::PRESENT - PageTableFlags::ACCESSED - PageTableFlags::DIRTY;
+    entry.set_addr(PhysAddr::new(slot * PAGE_SIZE), flags | SWAPPED);
+}
+
+// maps `frame` with the flags the page had before it went to swap
+pub(super) fn set_restored(entry: &mut PageTableEntry, frame: PhysFrame) {
+    let flags = entry.flags() - SWAPPED;
+    entry.set_addr(frame.start_address(), flags | PageTableFlags::PRESENT);
+}
+
+// past the header page
+fn first_sector(slot: u64) -> u64 {
+    (slot + 1) * SECTORS_PER_SLOT
+}
+
+unsafe fn frame_bytes(frame: PhysFrame) -> &'static mut [u8] {
+    let virt = physical_memory_offset() + frame.start_address().as_u64();
+    slice::from_raw_parts_mut(virt.as_mut_ptr::<u8>(), PAGE_SIZE as usize)
+}
@@ -0,0 +1,177 @@
+/**************************************************************************************************
+* Name : 									  syscall.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					  System Calls using syscall/sysret
+* Version : 									 0.1
+**************************************************************************************************/
+
+use crate::{gdt, interrupts, memory::{self, address_space, protection}, print};
+use core::{arch::global_asm, str};
+use x86_64::{
+    VirtAddr,
+    registers::{
+        model_specific::{Efer, EferFlags, LStar, SFMask, Star},
+        rflags::RFlags,
+    },
+};
+
+pub const SYS_WRITE: u64 = 0;
+pub const SYS_UPTIME: u64 = 1;
+
+/*************************************
+* Returned for unknown system calls
+	and for invalid arguments
+*************************************/
+pub const ENOSYS: u64 = u64::MAX;
+pub const EFAULT: u64 = u64::MAX - 1;
+
+pub type SyscallHandler = fn(args: [u64; 5]) -> u64;
+
+/******************************************
+* Indexed by the syscall number in rax
+******************************************/
+static SYSCALL_TABLE: [Option<SyscallHandler>; 2] = [
+    Some(sys_write),
+    Some(sys_uptime),
+];
+
+static mut KERNEL_STACK_TOP: u64 = 0;
+static mut USER_STACK_POINTER: u64 = 0;
+
+pub fn init() {
+    let selectors = gdt::selectors();
+
+    Star::write(
+        selectors.user_code_selector,
+        selectors.user_data_selector,
+        selectors.code_selector,
+        selectors.data_selector,
+    ).expect("GDT layout doesn't fit syscall/sysret");
+
+    unsafe {
+        KERNEL_STACK_TOP = gdt::kernel_stack_top().as_u64();
+    }
+
+    LStar::write(VirtAddr::new(syscall_entry as usize as u64));
+
+    /***************************************************
+    * keep interrupts off until the entry point is on
+    	the kernel stack, and user mode can't hand us
+    	access to its pages with AC
+    ***************************************************/
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::DIRECTION_FLAG | RFlags::TRAP_FLAG | RFlags::ALIGNMENT_CHECK);
+
+    unsafe {
+        Efer::update(|flags| *flags |= EferFlags::SYSTEM_CALL_EXTENSIONS);
+    }
+}
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/*************************************************************************
+* rax holds the syscall number, rdi, rsi, rdx, r10 and r8 the arguments.
+	`syscall` put the user rip in rcx and rflags in r11. Everything but
+	rax, rcx and r11 is given back to user mode unchanged.
+*************************************************************************/
+global_asm!(
+    ".global syscall_entry",
+    "syscall_entry:",
+    "mov [rip + {user_stack}], rsp",
+    "mov rsp, [rip + {kernel_stack}]",
+    "push qword ptr [rip + {user_stack}]",
+    "push rcx",
+    "push r11",
+    "push rdi",
+    "push rsi",
+    "push rdx",
+    "push r8",
+    "push r9",
+    "push r10",
+    "sub rsp, 8",
+    "mov r9, r8",
+    "mov r8, r10",
+    "mov rcx, rdx",
+    "mov rdx, rsi",
+    "mov rsi, rdi",
+    "mov rdi, rax",
+    "call {dispatch}",
+    "add rsp, 8",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdx",
+    "pop rsi",
+    "pop rdi",
+    "pop r11",
+    "pop rcx",
+    "pop rsp",
+    "sysretq",
+    user_stack = sym USER_STACK_POINTER,
+    kernel_stack = sym KERNEL_STACK_TOP,
+    dispatch = sym syscall_dispatch,
+);
+
+extern "C" fn syscall_dispatch(number: u64, arg0: u64, arg1: u64, arg2: u64, arg3: u64, arg4: u64) -> u64 {
+    dispatch(number, [arg0, arg1, arg2, arg3, arg4])
+}
+
+/*************************************
+* Runs a system call the way the entry
+	point does, without leaving ring 0
+*************************************/
+pub fn dispatch(number: u64, args: [u64; 5]) -> u64 {
+    match SYSCALL_TABLE.get(number as usize) {
+        Some(Some(handler)) => handler(args),
+        _ => ENOSYS,
+    }
+}
+
+/****************************************
+* Only hands out memory user mode is
+	actually allowed to touch. Reading it
+	needs `with_user_access` under SMAP.
+****************************************/
+fn user_slice(ptr: u64, len: u64) -> Option<&'static [u8]> {
+    if len == 0 {
+        return Some(&[]);
+    }
+
+    let end = ptr.checked_add(len - 1)?;
+    let start = VirtAddr::try_new(ptr).ok()?;
+    let end = VirtAddr::try_new(end).ok()?;
+
+    // pages that weren't touched yet are backed by the page fault handler once they're read
+    let space = address_space::current();
+    let mut page = start.align_down(4096u64);
+    while page <= end {
+        let in_area = space.as_ref().map_or(false, |space| space.area(page).is_some());
+        if !in_area && !memory::user_accessible(page, false) {
+            return None;
+        }
+        page += 4096u64;
+    }
+
+    Some(unsafe { core::slice::from_raw_parts(start.as_ptr(), len as usize) })
+}
+
+fn sys_write(args: [u64; 5]) -> u64 {
+    let bytes = match user_slice(args[0], args[1]) {
+        Some(bytes) => bytes,
+        None => return EFAULT,
+    };
+
+    protection::with_user_access(|| match str::from_utf8(bytes) {
+        Ok(text) => {
+            print!("{}", text);
+            bytes.len() as u64
+        }
+        Err(_) => EFAULT,
+    })
+}
+
+fn sys_uptime(_args: [u64; 5]) -> u64 {
+    interrupts::get_index()
+}
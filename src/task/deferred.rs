@@ -0,0 +1,137 @@
+/**************************************************************************************************
+* Name : 								  task/deferred.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				   Deferred Interrupt Work (Bottom Halves)
+* Version : 									 0.1
+**************************************************************************************************/
+
+/**************************************************************************************
+* Interrupt handlers are the top half: they talk to the device, queue a `Work` item
+	and return. The executor polls `run` like any other task and runs the queued
+	items later with interrupts enabled, that's the bottom half.
+**************************************************************************************/
+
+use conquer_once::spin::OnceCell;
+use core::{pin::Pin, sync::atomic::{AtomicU64, Ordering}, task::{Context, Poll}};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{task::AtomicWaker, stream::{Stream, StreamExt}};
+
+const WORK_QUEUE_SIZE: usize = 256;
+
+static WORK_QUEUE: OnceCell<ArrayQueue<Work>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+static SCHEDULED: AtomicU64 = AtomicU64::new(0);
+static COMPLETED: AtomicU64 = AtomicU64::new(0);
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/*******************************************************
+* A function and its argument, it doesn't allocate so
+	it can be queued from interrupt context
+*******************************************************/
+#[derive(Debug, Clone, Copy)]
+pub struct Work {
+    function: fn(u64),
+    data: u64,
+}
+
+impl Work {
+    pub const fn new(function: fn(u64), data: u64) -> Self {
+        Work {
+            function,
+            data,
+        }
+    }
+
+    fn run(self) {
+        (self.function)(self.data);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorkStats {
+    pub scheduled: u64,
+    pub completed: u64,
+    pub dropped: u64,
+}
+
+pub fn init() {
+    WORK_QUEUE.try_init_once(|| ArrayQueue::new(WORK_QUEUE_SIZE))
+        .expect("deferred::init should only be called once");
+}
+
+/*******************************************************
+* Queues work for the bottom half, safe to call from
+	interrupt handlers. Gives the work back if the queue
+	is full or not initialized yet.
+*******************************************************/
+pub fn schedule(work: Work) -> Result<(), Work> {
+    let queue = match WORK_QUEUE.try_get() {
+        Ok(queue) => queue,
+        Err(_) => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            return Err(work);
+        }
+    };
+
+    match queue.push(work) {
+        Ok(()) => {
+            SCHEDULED.fetch_add(1, Ordering::Relaxed);
+            WAKER.wake();
+            Ok(())
+        }
+        Err(crossbeam_queue::PushError(work)) => {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            Err(work)
+        }
+    }
+}
+
+pub fn stats() -> WorkStats {
+    WorkStats {
+        scheduled: SCHEDULED.load(Ordering::Relaxed),
+        completed: COMPLETED.load(Ordering::Relaxed),
+        dropped: DROPPED.load(Ordering::Relaxed),
+    }
+}
+
+struct WorkStream {
+    _private: (),
+}
+
+impl Stream for WorkStream {
+    type Item = Work;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Work>> {
+        let queue = WORK_QUEUE
+            .try_get()
+            .expect("work queue not initialized");
+
+        // fast path
+        if let Ok(work) = queue.pop() {
+            return Poll::Ready(Some(work));
+        }
+
+        WAKER.register(&cx.waker());
+        match queue.pop() {
+            Ok(work) => {
+                WAKER.take();
+                Poll::Ready(Some(work))
+            }
+            Err(crossbeam_queue::PopError) => Poll::Pending,
+        }
+    }
+}
+
+/*******************************************
+* The bottom half, spawn this on the executor
+*******************************************/
+pub async fn run() {
+    let mut work_items = WorkStream { _private: () };
+
+    while let Some(work) = work_items.next().await {
+        work.run();
+        COMPLETED.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -1,38 +1,135 @@
 /**************************************************************************************************
-* Name :                                 task/executor.rs
+* Name : 								  task/executor.rs
 * Author : 										Avery
 * Date : 									  2/01/2023
 * Purpose : 					       Task Executor & Wakers
 * Version : 									 0.1
 **************************************************************************************************/
 
-use super::{Task, TaskId};
-use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
-use core::{task::{Waker, Context, Poll}, borrow::BorrowMut};
-use crossbeam_queue::ArrayQueue;
+use super::{registry::{self, Priority, TaskInfo}, spawner::{self, Spawned, Spawner}, Task, TaskId};
+use crate::memory::address_space;
+use alloc::{collections::{BTreeMap, VecDeque}, sync::Arc, task::Wake};
+use core::{arch::x86_64::_rdtsc, ptr, sync::atomic::{AtomicPtr, Ordering}, task::{Waker, Context, Poll}, borrow::BorrowMut};
+use crossbeam_queue::SegQueue;
 use x86_64::instructions::interrupts::{self, enable_and_hlt};
 
+/*********************************************************
+* How many polls each priority gets per round, highest
+	first. A level that has used its share waits for the
+	next round while the levels below it get theirs.
+*********************************************************/
+const ROUND_BUDGET: [usize; Priority::COUNT] = [32, 16, 8];
+
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    ready: Arc<ReadyList>,
+    // ready tasks by priority, each polled in turn
+    levels: [VecDeque<Arc<TaskInfo>>; Priority::COUNT],
     waker_cache: BTreeMap<TaskId, Waker>,
+    // futures from spawners, they become tasks before the next poll
+    spawned: Arc<SegQueue<Spawned>>,
+}
+
+/**************************************************************************************
+* The tasks woken since the executor last looked, linked through their own TaskInfo
+	so waking never allocates and the list can't fill up. A task's `scheduled` flag
+	keeps it on the list once however often it is woken. Wakers push from anywhere,
+	interrupt handlers included; only the executor takes, all at once.
+**************************************************************************************/
+struct ReadyList {
+    head: AtomicPtr<TaskInfo>,
+}
+
+impl ReadyList {
+    fn new() -> Self {
+        ReadyList { head: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.load(Ordering::SeqCst).is_null()
+    }
+
+    // false if the task was on the list already
+    fn push(&self, info: &Arc<TaskInfo>) -> bool {
+        if info.scheduled.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+
+        // the list holds a reference, given back when it is taken off
+        let node = Arc::into_raw(info.clone()) as *mut TaskInfo;
+        let mut head = self.head.load(Ordering::SeqCst);
+        loop {
+            info.next.store(head, Ordering::SeqCst);
+            match self.head.compare_exchange_weak(head, node, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return true,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    // empties the list, the tasks come out in the order they were woken
+    fn take(&self) -> ReadyTasks {
+        let mut node = self.head.swap(ptr::null_mut(), Ordering::SeqCst);
+        let mut reversed = ptr::null_mut();
+        while !node.is_null() {
+            let info = unsafe { &*node };
+            let next = info.next.load(Ordering::SeqCst);
+            info.next.store(reversed, Ordering::SeqCst);
+            reversed = node;
+            node = next;
+        }
+        ReadyTasks { next: reversed }
+    }
+}
+
+impl Drop for ReadyList {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+struct ReadyTasks {
+    next: *mut TaskInfo,
+}
+
+impl Iterator for ReadyTasks {
+    type Item = Arc<TaskInfo>;
+
+    // still scheduled until the executor polls it
+    fn next(&mut self) -> Option<Arc<TaskInfo>> {
+        if self.next.is_null() {
+            return None;
+        }
+        let info = unsafe { Arc::from_raw(self.next) };
+        self.next = info.next.swap(ptr::null_mut(), Ordering::SeqCst);
+        Some(info)
+    }
+}
+
+// what wasn't gone through only has its references given back
+impl Drop for ReadyTasks {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
 }
 
 struct TaskWaker {
-    task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    ready: Arc<ReadyList>,
+    info: Arc<TaskInfo>,
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+    fn new(ready: Arc<ReadyList>, info: Arc<TaskInfo>) -> Waker {
         Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
+            ready,
+            info,
         }))
     }
 
+    // waking a task that is already scheduled does nothing
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task_queue full");
+        self.info.woken();
+        self.ready.push(&self.info);
     }
 }
 
@@ -50,21 +147,43 @@ impl Executor {
     pub fn new() -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
+            ready: Arc::new(ReadyList::new()),
+            levels: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
             waker_cache: BTreeMap::new(),
+            spawned: Arc::new(SegQueue::new()),
         }
     }
 
+    pub fn spawner(&self) -> Spawner {
+        Spawner::new(self.spawned.clone())
+    }
+
+    /*************************************************
+    * Runs tasks forever, from now on `task::spawner`
+    	spawns on this executor
+    *************************************************/
     pub fn run(&mut self) -> ! {
+        spawner::set_global(self.spawner());
         loop {
             self.run_ready_tasks();
             self.sleep_if_idle();
         }
     }
 
+    // polls until no task is ready anymore, the ones waiting for something stay
+    pub fn run_until_idle(&mut self) {
+        while !self.is_idle() {
+            self.run_ready_tasks();
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.ready.is_empty() && self.spawned.is_empty() && self.levels.iter().all(VecDeque::is_empty)
+    }
+
     fn sleep_if_idle(&self) {
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if self.is_idle() {
             enable_and_hlt();
         } else {
             interrupts::enable();
@@ -72,41 +191,92 @@ impl Executor {
     }
 
     pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
+        let task_id = task.id();
+        if self.tasks.insert(task_id, task).is_some() {
             panic!("task with same ID already in tasks");
         }
-        self.task_queue.push(task_id).expect("queue full");
+        self.ready.push(&self.tasks[&task_id].info);
     }
 
     fn run_ready_tasks(&mut self) {
+        while let Ok(spawned) = self.spawned.pop() {
+            self.spawn(Task::with_info(spawned.future, spawned.info));
+        }
+
     /**************************************************
     * destructure `self` to avoid borrow checker errors
     **************************************************/
         let Self {
             tasks,
-            task_queue,
+            ready,
+            levels,
             waker_cache,
+            ..
         } = self;
 
-        while let Ok(task_id) = task_queue.pop() {
+    /**************************************************
+    * One round: the highest level with budget left goes
+    	next. Tasks woken meanwhile are sorted in before
+    	every poll, so input doesn't wait for the round.
+    **************************************************/
+        let mut budget = ROUND_BUDGET;
+        loop {
+            for info in ready.take() {
+                levels[info.priority() as usize].push_back(info);
+            }
+
+            let level = match (0..Priority::COUNT).find(|&level| budget[level] > 0 && !levels[level].is_empty()) {
+                Some(level) => level,
+                None => break,
+            };
+            budget[level] -= 1;
+
+            // off the levels, a wake from now on schedules it again
+            let info = levels[level].pop_front().unwrap();
+            info.scheduled.store(false, Ordering::SeqCst);
+            let task_id = info.id;
             let task = match tasks.get_mut(&task_id) {
                 Some(task) => task,
                 None => continue, // task no longer exists
             };
+
+            // aborted -> dropped without another poll
+            if task.info.is_aborted() {
+                tasks.remove(&task_id);
+                waker_cache.remove(&task_id);
+                continue;
+            }
+
             let waker = waker_cache
                 .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+                .or_insert_with(|| {
+                    let waker = TaskWaker::new(ready.clone(), task.info.clone());
+                    task.info.set_waker(&waker);
+                    waker
+                });
             let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
+            address_space::switch_to(task.address_space());
+
+            task.info.start_poll();
+            registry::set_current(Some(task.info.clone()));
+            let start = unsafe { _rdtsc() };
+            let result = task.poll(&mut context);
+            task.info.add_poll_time(unsafe { _rdtsc() }.wrapping_sub(start));
+            registry::set_current(None);
+
+            match result {
                 Poll::Ready(()) => {
                 /********************************************
                 * task done -> remove it and its cached waker
+                	and let go of its address space
                 *********************************************/
+                    if task.address_space().is_some() {
+                        address_space::switch_to(None);
+                    }
                     tasks.remove(&task_id);
                     waker_cache.remove(&task_id);
                 }
-                Poll::Pending => {}
+                Poll::Pending => task.info.end_poll(),
             }
         }
     }
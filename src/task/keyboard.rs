@@ -9,14 +9,17 @@
 use alloc::boxed::Box;
 use conquer_once::spin::{OnceCell};
 use crossbeam_queue::ArrayQueue;
-use crate::{print, println, change_fg, cmd, application::{self, Application}, vga_driver};
+use crate::{print, println, change_fg, cmd, application::{self, Application}, vga_driver, interrupts::{self, InterruptIndex, IrqReturn}, task::deferred::{self, Work}};
 use vga::colors::Color16;
 use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1, KeyCode};
-use core::{pin::Pin, task::{Poll, Context}};
+use core::{pin::Pin, task::{Poll, Context}, sync::atomic::{AtomicBool, AtomicU64, Ordering}};
 use futures_util::{task::AtomicWaker, stream::{Stream, StreamExt}};
 
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 static WAKER: AtomicWaker = AtomicWaker::new();
+static DROPPED_SCANCODES: AtomicU64 = AtomicU64::new(0);
+// a report of the dropped scancodes is queued
+static REPORT_SCHEDULED: AtomicBool = AtomicBool::new(false);
 
 pub static mut INPUT_TARGET: InputTarget = InputTarget::None;
 pub static mut APPLICATION: Application = Application::new_unrunnable("None");
@@ -67,18 +70,62 @@ impl Stream for ScancodeStream {
     }
 }
 
+pub fn init() {
+    interrupts::register_irq(InterruptIndex::Keyboard.irq_line(), "keyboard", keyboard_interrupt)
+        .expect("failed to register the keyboard interrupt");
+}
+
+fn keyboard_interrupt(_vector: u8) -> IrqReturn {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let scancode: u8 = unsafe { port.read() };
+    add_scancode(scancode);
+
+    IrqReturn::Handled
+}
+
+/***********************************************
+* Top half, called from the keyboard interrupt.
+	Warnings are printed later by `report_dropped`
+***********************************************/
 pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            WAKER.wake();
-        }
+    let pushed = match SCANCODE_QUEUE.try_get() {
+        Ok(queue) => queue.push(scancode).is_ok(),
+        Err(_) => false,
+    };
+
+    if pushed {
+        WAKER.wake();
+        return;
+    }
+
+    /**************************************************
+    * one report for all drops until it runs. If it
+    	can't be queued (the work queue is full or not
+    	there yet) the next drop tries again.
+    **************************************************/
+    DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+    if !REPORT_SCHEDULED.swap(true, Ordering::SeqCst) && deferred::schedule(Work::new(report_dropped, 0)).is_err() {
+        REPORT_SCHEDULED.store(false, Ordering::SeqCst);
+    }
+}
+
+fn report_dropped(_data: u64) {
+    REPORT_SCHEDULED.store(false, Ordering::SeqCst);
+    let dropped = DROPPED_SCANCODES.swap(0, Ordering::Relaxed);
+    // an earlier report took them already
+    if dropped == 0 {
+        return;
+    }
+
+    change_fg!(Color16::Yellow);
+    if SCANCODE_QUEUE.try_get().is_ok() {
+        println!("WARNING: scancode queue full; dropped {} keyboard inputs", dropped);
     } else {
-        change_fg!(Color16::Yellow);
-        println!("WARNING: scancode queue uninitialized");
-        change_fg!(Color16::White);
+        println!("WARNING: scancode queue uninitialized; dropped {} keyboard inputs", dropped);
     }
+    change_fg!(Color16::White);
 }
 
 async fn cmd_keypress_override(key: DecodedKey) -> (bool, DecodedKey) {
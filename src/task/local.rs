@@ -0,0 +1,54 @@
+/**************************************************************************************************
+* Name : 								    task/local.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					  Values Stored per Task under a Key
+* Version : 									 0.1
+**************************************************************************************************/
+
+/*********************************************************
+* Every task has its own values, found by a key and the
+	type they were stored with. They belong to the task
+	being polled and go away with it. Outside of a task
+	there is nothing to store them in.
+*********************************************************/
+
+use super::registry;
+use alloc::boxed::Box;
+use core::any::Any;
+
+// stores `value` under `key`, false if no task is running
+pub fn set<T: Any + Send>(key: &'static str, value: T) -> bool {
+    match registry::current() {
+        Some(task) => {
+            task.locals.lock().insert(key, Box::new(value));
+            true
+        }
+        None => false,
+    }
+}
+
+// a copy of the value under `key`, if it is a `T`
+pub fn get<T: Any + Clone>(key: &'static str) -> Option<T> {
+    let task = registry::current()?;
+    let locals = task.locals.lock();
+    locals.get(key)?.downcast_ref::<T>().cloned()
+}
+
+/*********************************************************
+* Calls `f` with the value under `key` if it is a `T`.
+	It is taken out meanwhile, so `f` can use other task
+	locals but won't see this one.
+*********************************************************/
+pub fn with<T: Any + Send, R>(key: &'static str, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+    let task = registry::current()?;
+    let mut value = task.locals.lock().remove(key)?;
+
+    let result = value.downcast_mut::<T>().map(f);
+    task.locals.lock().entry(key).or_insert(value);
+    result
+}
+
+pub fn remove(key: &'static str) -> bool {
+    registry::current().map_or(false, |task| task.locals.lock().remove(key).is_some())
+}
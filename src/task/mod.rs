@@ -10,33 +10,112 @@
 pub mod executor;
 pub mod simple_executor;
 pub mod keyboard;
+pub mod deferred;
+pub mod spawner;
+pub mod registry;
+pub mod sync;
+pub mod local;
+
+pub use spawner::{spawner, JoinError, JoinHandle, Spawner};
+pub use registry::{AbortHandle, Priority, TaskState, TaskStats};
 
 use core::{task::{Context, Poll}, future::Future, pin::Pin, sync::atomic::{AtomicU64, Ordering}};
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String, sync::Arc};
+use registry::TaskInfo;
 
-use crate::print;
+use crate::{memory::address_space::AddressSpace, print};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct TaskId(u64);
 
 pub struct Task {
-    id: TaskId,
+    info: Arc<TaskInfo>,
     future: Pin<Box<dyn Future<Output = ()>>>,
+    address_space: Option<Arc<AddressSpace>>,
 }
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+        Task::with_info(Box::pin(future), TaskInfo::new(None))
+    }
+
+    // the name is what `ps` shows for it
+    pub fn named(name: &str, future: impl Future<Output = ()> + 'static) -> Task {
+        Task::with_info(Box::pin(future), TaskInfo::new(Some(String::from(name))))
+    }
+
+    fn with_info(future: Pin<Box<dyn Future<Output = ()>>>, info: Arc<TaskInfo>) -> Task {
+        registry::register(&info);
         Task {
-            id: TaskId::new(),
-            future: Box::pin(future),
+            info,
+            future,
+            address_space: None,
         }
     }
 
+    /*********************************************
+    * A task that is polled with `address_space`
+    	loaded into CR3
+    *********************************************/
+    pub fn with_address_space(future: impl Future<Output = ()> + 'static, address_space: Arc<AddressSpace>) -> Task {
+        let mut task = Task::new(future);
+        task.address_space = Some(address_space);
+        task
+    }
+
+    // Priority::Ui unless set here
+    pub fn with_priority(self, priority: Priority) -> Task {
+        self.info.set_priority(priority);
+        self
+    }
+
+    pub fn address_space(&self) -> Option<&Arc<AddressSpace>> {
+        self.address_space.as_ref()
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle::new(self.info.clone())
+    }
+
+    fn id(&self) -> TaskId {
+        self.info.id
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
         self.future.as_mut().poll(context)
     }
 }
 
+// finished or aborted, its future and locals are dropped with it
+impl Drop for Task {
+    fn drop(&mut self) {
+        registry::unregister(&self.info);
+    }
+}
+
+/*********************************************
+* Lets the other ready tasks of its priority
+	run once before the caller goes on
+*********************************************/
+pub async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    YieldNow(false).await
+}
+
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
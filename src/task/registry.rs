@@ -0,0 +1,257 @@
+/**************************************************************************************************
+* Name : 								  task/registry.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				  Live Tasks, their State and Abort Handles
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::TaskId;
+use crate::lock::Mutex;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::{any::Any, ptr, sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicU8, Ordering}, task::Waker};
+
+/*********************************************************
+* Every task that exists right now, whichever executor
+	it is on. Tasks add themselves when they're made and
+	go away when they're dropped.
+*********************************************************/
+static TASKS: Mutex<BTreeMap<TaskId, Arc<TaskInfo>>> = Mutex::new(BTreeMap::new());
+
+// the task being polled right now
+static CURRENT: Mutex<Option<Arc<TaskInfo>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    // woken, waiting for the executor to get to it
+    Ready,
+    Running,
+    // returned Pending, nothing has woken it yet
+    Waiting,
+    // aborted, the executor drops it the next time it comes up
+    Aborting,
+}
+
+impl TaskState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => TaskState::Ready,
+            1 => TaskState::Running,
+            2 => TaskState::Waiting,
+            _ => TaskState::Aborting,
+        }
+    }
+}
+
+/*********************************************************
+* Which ready task the executor polls first. Higher ones
+	go first but only for a share of every round, so the
+	lower ones still get polled.
+*********************************************************/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Input,
+    Ui,
+    Background,
+}
+
+impl Priority {
+    pub const COUNT: usize = 3;
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Priority::Input,
+            1 => Priority::Ui,
+            _ => Priority::Background,
+        }
+    }
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Priority::Ui
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskStats {
+    pub id: u64,
+    pub name: Option<String>,
+    pub state: TaskState,
+    pub priority: Priority,
+    pub polls: u64,
+    // time spent in its polls, in TSC cycles
+    pub poll_cycles: u64,
+    pub max_poll_cycles: u64,
+}
+
+/*********************************************************
+* What a task shares with abort handles, the registry and
+	the executor. The waker is the executor's, abort uses
+	it to get the task dropped. `scheduled` and `next` are
+	the executor's ready list, a task is on it at most once.
+*********************************************************/
+pub(super) struct TaskInfo {
+    pub(super) id: TaskId,
+    name: Option<String>,
+    state: AtomicU8,
+    priority: AtomicU8,
+    polls: AtomicU64,
+    poll_cycles: AtomicU64,
+    max_poll_cycles: AtomicU64,
+    aborted: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+    pub(super) locals: Mutex<BTreeMap<&'static str, Box<dyn Any + Send>>>,
+    pub(super) scheduled: AtomicBool,
+    pub(super) next: AtomicPtr<TaskInfo>,
+}
+
+impl TaskInfo {
+    pub(super) fn new(name: Option<String>) -> Arc<TaskInfo> {
+        Arc::new(TaskInfo {
+            id: TaskId::new(),
+            name,
+            state: AtomicU8::new(TaskState::Ready as u8),
+            priority: AtomicU8::new(Priority::default() as u8),
+            polls: AtomicU64::new(0),
+            poll_cycles: AtomicU64::new(0),
+            max_poll_cycles: AtomicU64::new(0),
+            aborted: AtomicBool::new(false),
+            waker: Mutex::new(None),
+            locals: Mutex::new(BTreeMap::new()),
+            scheduled: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+
+    pub(super) fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn priority(&self) -> Priority {
+        Priority::from_u8(self.priority.load(Ordering::Relaxed))
+    }
+
+    // takes effect the next time the task is woken
+    pub(super) fn set_priority(&self, priority: Priority) {
+        self.priority.store(priority as u8, Ordering::Relaxed);
+    }
+
+    pub(super) fn set_waker(&self, waker: &Waker) {
+        *self.waker.lock() = Some(waker.clone());
+    }
+
+    pub(super) fn woken(&self) {
+        if !self.is_aborted() {
+            self.state.store(TaskState::Ready as u8, Ordering::SeqCst);
+        }
+    }
+
+    pub(super) fn start_poll(&self) {
+        self.state.store(TaskState::Running as u8, Ordering::SeqCst);
+        self.polls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_poll_time(&self, cycles: u64) {
+        self.poll_cycles.fetch_add(cycles, Ordering::Relaxed);
+        self.max_poll_cycles.fetch_max(cycles, Ordering::Relaxed);
+    }
+
+    // a wake during the poll has made it ready already
+    pub(super) fn end_poll(&self) {
+        let _ = self.state.compare_exchange(
+            TaskState::Running as u8, TaskState::Waiting as u8, Ordering::SeqCst, Ordering::SeqCst,
+        );
+    }
+
+    fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.state.store(TaskState::Aborting as u8, Ordering::SeqCst);
+        let waker = self.waker.lock().clone();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    fn stats(&self) -> TaskStats {
+        TaskStats {
+            id: self.id.0,
+            name: self.name.clone(),
+            state: TaskState::from_u8(self.state.load(Ordering::SeqCst)),
+            priority: self.priority(),
+            polls: self.polls.load(Ordering::Relaxed),
+            poll_cycles: self.poll_cycles.load(Ordering::Relaxed),
+            max_poll_cycles: self.max_poll_cycles.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/*********************************************************
+* Stops a task from outside: its future is dropped the
+	next time the executor gets to it, without another
+	poll, and whoever waits on its JoinHandle is woken
+*********************************************************/
+#[derive(Clone)]
+pub struct AbortHandle {
+    info: Arc<TaskInfo>,
+}
+
+impl AbortHandle {
+    pub(super) fn new(info: Arc<TaskInfo>) -> Self {
+        AbortHandle { info }
+    }
+
+    pub fn abort(&self) {
+        self.info.abort();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.info.is_aborted()
+    }
+
+    pub fn id(&self) -> u64 {
+        self.info.id.0
+    }
+}
+
+pub(super) fn register(info: &Arc<TaskInfo>) {
+    TASKS.lock().insert(info.id, info.clone());
+}
+
+/*********************************************************
+* Called when a task is dropped. The waker it holds has a
+	reference back to it through the executor, that cycle
+	is broken here. Its locals go with it.
+*********************************************************/
+pub(super) fn unregister(info: &TaskInfo) {
+    TASKS.lock().remove(&info.id);
+    info.waker.lock().take();
+    let locals = core::mem::take(&mut *info.locals.lock());
+    drop(locals);
+}
+
+pub(super) fn set_current(info: Option<Arc<TaskInfo>>) {
+    *CURRENT.lock() = info;
+}
+
+pub(super) fn current() -> Option<Arc<TaskInfo>> {
+    CURRENT.lock().clone()
+}
+
+// the live tasks, oldest first
+pub fn tasks() -> Vec<TaskStats> {
+    TASKS.lock().values().map(|info| info.stats()).collect()
+}
+
+// an abort handle for the live task with `id`
+pub fn abort_handle(id: u64) -> Option<AbortHandle> {
+    TASKS.lock().get(&TaskId(id)).map(|info| AbortHandle::new(info.clone()))
+}
+
+pub fn current_id() -> Option<u64> {
+    CURRENT.lock().as_ref().map(|info| info.id.0)
+}
+
+pub fn current_name() -> Option<String> {
+    CURRENT.lock().as_ref().and_then(|info| info.name.clone())
+}
@@ -0,0 +1,193 @@
+/**************************************************************************************************
+* Name : 								   task/spawner.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				    Spawning Tasks while the Executor Runs
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::registry::{AbortHandle, Priority, TaskInfo};
+use crate::lock::Mutex;
+use alloc::{boxed::Box, string::String, sync::Arc};
+use core::{fmt, future::Future, pin::Pin, task::{Context, Poll, Waker}};
+use crossbeam_queue::SegQueue;
+
+// a future on its way to the executor, its task info is made up front for the handles
+pub(super) struct Spawned {
+    pub(super) future: Pin<Box<dyn Future<Output = ()> + Send>>,
+    pub(super) info: Arc<TaskInfo>,
+}
+
+/*********************************************************
+* The spawner of the executor that `Executor::run` was
+	called on, the one everything after boot runs on
+*********************************************************/
+static SPAWNER: Mutex<Option<Spawner>> = Mutex::new(None);
+
+/*********************************************************
+* Hands futures to an executor without borrowing it. They
+	wait in a queue the executor empties before it polls
+	anything else. Allocates, so not for interrupt handlers.
+*********************************************************/
+#[derive(Clone)]
+pub struct Spawner {
+    queue: Arc<SegQueue<Spawned>>,
+}
+
+impl Spawner {
+    pub(super) fn new(queue: Arc<SegQueue<Spawned>>) -> Self {
+        Spawner { queue }
+    }
+
+    /*****************************************************
+    * Runs `future` as a task of its own. The handle can
+    	be awaited for its output or dropped, the task runs
+    	to the end either way.
+    *****************************************************/
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_task(None, Priority::default(), future)
+    }
+
+    // same as `spawn`, `ps` shows the task as `name`
+    pub fn spawn_named<F>(&self, name: &str, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_task(Some(String::from(name)), Priority::default(), future)
+    }
+
+    // same as `spawn`, polled ahead of or behind other tasks by `priority`
+    pub fn spawn_with_priority<F>(&self, priority: Priority, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.spawn_task(None, priority, future)
+    }
+
+    fn spawn_task<F>(&self, name: Option<String>, priority: Priority, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(JoinState { output: None, finished: false, joined: false, waker: None }));
+        let completion = Completion { state: state.clone() };
+        let info = TaskInfo::new(name);
+        info.set_priority(priority);
+        let abort = AbortHandle::new(info.clone());
+
+        self.queue.push(Spawned {
+            future: Box::pin(async move {
+                completion.finish(future.await);
+            }),
+            info,
+        });
+        JoinHandle { state, abort }
+    }
+}
+
+// makes `spawner` the one `spawner()` returns
+pub(super) fn set_global(spawner: Spawner) {
+    *SPAWNER.lock() = Some(spawner);
+}
+
+// None until the kernel's executor runs
+pub fn spawner() -> Option<Spawner> {
+    SPAWNER.lock().clone()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    // the future was dropped before it returned anything
+    Aborted,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Aborted => write!(f, "task was aborted"),
+        }
+    }
+}
+
+struct JoinState<T> {
+    output: Option<T>,
+    finished: bool,
+    joined: bool,
+    waker: Option<Waker>,
+}
+
+/*********************************************************
+* Owned by the spawned future. However the future goes,
+	returning or dropped by an abort, this marks the task
+	finished and wakes whoever is joining it.
+*********************************************************/
+struct Completion<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn finish(self, output: T) {
+        self.state.lock().output = Some(output);
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut state = self.state.lock();
+            state.finished = true;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/*********************************************************
+* Resolves to what a spawned task returned, or an error
+	if it was aborted first. Waits until the task is done,
+	the task wakes it then.
+*********************************************************/
+pub struct JoinHandle<T> {
+    state: Arc<Mutex<JoinState<T>>>,
+    abort: AbortHandle,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().finished
+    }
+
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    // for stopping the task from somewhere the handle isn't
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        assert!(!state.joined, "JoinHandle polled after it returned");
+
+        if state.finished {
+            state.joined = true;
+            return Poll::Ready(state.output.take().ok_or(JoinError::Aborted));
+        }
+
+        state.waker = Some(context.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,118 @@
+/**************************************************************************************************
+* Name : 								    task/sync.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			   Channels, Locks and Signals between Async Tasks
+* Version : 									 0.1
+**************************************************************************************************/
+
+/**************************************************************************************
+* Waiting tasks park their waker and return Pending, whoever releases or sends wakes
+	them through the executor like any other wakeup. The state of every primitive is
+	behind a spinlock that is only held with interrupts disabled, so an interrupt
+	handler can't find it locked on this CPU. Handlers may send on a bounded channel
+	with `try_send`, send on a oneshot, notify and add permits; none of those
+	allocate. The other side of each is for tasks.
+**************************************************************************************/
+
+pub mod mpsc;
+pub mod oneshot;
+pub mod mutex;
+pub mod rwlock;
+pub mod semaphore;
+pub mod notify;
+
+pub use mutex::{Mutex, MutexGuard};
+pub use notify::Notify;
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use semaphore::{Semaphore, SemaphorePermit};
+
+use alloc::collections::VecDeque;
+use core::task::Waker;
+use x86_64::instructions::interrupts;
+
+// runs `f` on what `lock` guards without an interrupt getting in between
+fn with_lock<T, R>(lock: &crate::lock::Mutex<T>, f: impl FnOnce(&mut T) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut lock.lock()))
+}
+
+/*********************************************************
+* Tasks waiting for something, oldest first. A waiter
+	keeps its key to update its waker or leave. Being
+	taken off the list is what tells it that it was woken.
+	Wakers are woken with the lock held, all they do is
+	queue their task.
+* Every waiting future takes its entry out when it is
+	dropped, so a waker on the list belongs to a live task
+	whose executor holds it too. Waking it from an
+	interrupt handler never frees the task.
+*********************************************************/
+struct WaitList {
+    next_key: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl WaitList {
+    const fn new() -> Self {
+        WaitList {
+            next_key: 0,
+            waiters: VecDeque::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    // adds the waiter, or swaps in its new waker if it is still waiting
+    fn register(&mut self, key: &mut Option<u64>, waker: &Waker) {
+        if let Some(key) = *key {
+            if let Some((_, old)) = self.waiters.iter_mut().find(|(other, _)| *other == key) {
+                if !old.will_wake(waker) {
+                    *old = waker.clone();
+                }
+                return;
+            }
+        }
+
+        let new = self.next_key;
+        self.next_key += 1;
+        self.waiters.push_back((new, waker.clone()));
+        *key = Some(new);
+    }
+
+    // true if `key` was woken already
+    fn was_woken(&self, key: Option<u64>) -> bool {
+        match key {
+            Some(key) => !self.waiters.iter().any(|(other, _)| *other == key),
+            None => false,
+        }
+    }
+
+    // takes the waiter off the list, false if it was woken already
+    fn remove(&mut self, key: u64) -> bool {
+        match self.waiters.iter().position(|(other, _)| *other == key) {
+            Some(index) => {
+                self.waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // wakes the oldest waiter, false if there was none
+    fn wake_one(&mut self) -> bool {
+        match self.waiters.pop_front() {
+            Some((_, waker)) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // emptied in place, so nothing is freed if an interrupt handler calls it
+    fn wake_all(&mut self) {
+        while self.wake_one() {}
+    }
+}
@@ -0,0 +1,305 @@
+/**************************************************************************************************
+* Name : 								  task/sync/mpsc.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			    Queues from Many Senders to One Receiving Task
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{with_lock, WaitList};
+use crate::lock;
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{fmt, future::Future, pin::Pin, task::{Context, Poll, Waker}};
+
+// the receiver is gone, the value comes back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "receiver dropped")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    // empty, and every sender is gone
+    Closed,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    // None for unbounded channels
+    capacity: Option<usize>,
+    senders: usize,
+    receiver_alive: bool,
+    receiver: Option<Waker>,
+    // senders waiting for room
+    waiting: WaitList,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity.map_or(false, |capacity| self.queue.len() >= capacity)
+    }
+
+    fn push(&mut self, value: T) {
+        self.queue.push_back(value);
+        if let Some(waker) = self.receiver.take() {
+            waker.wake();
+        }
+    }
+}
+
+type Shared<T> = Arc<lock::Mutex<State<T>>>;
+
+fn shared<T>(capacity: Option<usize>) -> Shared<T> {
+    Arc::new(lock::Mutex::new(State {
+        queue: VecDeque::with_capacity(capacity.unwrap_or(0)),
+        capacity,
+        senders: 1,
+        receiver_alive: true,
+        receiver: None,
+        waiting: WaitList::new(),
+    }))
+}
+
+/*********************************************************
+* A channel holding at most `capacity` values, senders
+	wait for room when it's full. The room is allocated up
+	front, so `try_send` works in interrupt handlers.
+*********************************************************/
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "a bounded channel needs room for a value");
+    let state = shared(Some(capacity));
+    (Sender { state: state.clone() }, Receiver { state })
+}
+
+/*********************************************************
+* A channel that grows as needed, sending never waits.
+	It allocates, so it isn't for interrupt handlers.
+*********************************************************/
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, Receiver<T>) {
+    let state = shared(None);
+    (UnboundedSender { state: state.clone() }, Receiver { state })
+}
+
+pub struct Sender<T> {
+    state: Shared<T>,
+}
+
+impl<T> Sender<T> {
+    // waits until there is room, fails if the receiver goes away
+    pub fn send(&self, value: T) -> SendFuture<'_, T> {
+        SendFuture { sender: self, value: Some(value), key: None }
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        with_lock(&self.state, |state| {
+            if !state.receiver_alive {
+                return Err(TrySendError::Closed(value));
+            }
+            if state.is_full() || !state.waiting.is_empty() {
+                return Err(TrySendError::Full(value));
+            }
+            state.push(value);
+            Ok(())
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        with_lock(&self.state, |state| !state.receiver_alive)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        with_lock(&self.state, |state| state.senders += 1);
+        Sender { state: self.state.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        drop_sender(&self.state);
+    }
+}
+
+pub struct UnboundedSender<T> {
+    state: Shared<T>,
+}
+
+impl<T> UnboundedSender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        with_lock(&self.state, |state| {
+            if !state.receiver_alive {
+                return Err(SendError(value));
+            }
+            state.push(value);
+            Ok(())
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        with_lock(&self.state, |state| !state.receiver_alive)
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        with_lock(&self.state, |state| state.senders += 1);
+        UnboundedSender { state: self.state.clone() }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T> {
+    fn drop(&mut self) {
+        drop_sender(&self.state);
+    }
+}
+
+// the last sender leaving wakes the receiver to find the channel closed
+fn drop_sender<T>(state: &Shared<T>) {
+    with_lock(state, |state| {
+        state.senders -= 1;
+        if state.senders == 0 {
+            if let Some(waker) = state.receiver.take() {
+                waker.wake();
+            }
+        }
+    });
+}
+
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    value: Option<T>,
+    key: Option<u64>,
+}
+
+// the value is only ever moved out, never pinned
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let value = this.value.take().expect("SendFuture polled after it returned");
+
+        with_lock(&this.sender.state, |state| {
+            if !state.receiver_alive {
+                return Poll::Ready(Err(SendError(value)));
+            }
+
+            let turn = state.waiting.was_woken(this.key) || state.waiting.is_empty();
+            if turn && !state.is_full() {
+                state.push(value);
+                this.key = None;
+                return Poll::Ready(Ok(()));
+            }
+
+            if state.waiting.was_woken(this.key) {
+                this.key = None;
+            }
+            state.waiting.register(&mut this.key, context.waker());
+            this.value = Some(value);
+            Poll::Pending
+        })
+    }
+}
+
+// woken for room it won't use, the next sender gets it
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            with_lock(&self.sender.state, |state| {
+                if !state.waiting.remove(key) && !state.is_full() {
+                    state.waiting.wake_one();
+                }
+            });
+        }
+    }
+}
+
+/*********************************************************
+* The one end values come out of, in the order they were
+	sent. Once every sender is gone and the queue is empty
+	`recv` returns None.
+*********************************************************/
+pub struct Receiver<T> {
+    state: Shared<T>,
+}
+
+impl<T> Receiver<T> {
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        with_lock(&self.state, |state| match state.queue.pop_front() {
+            Some(value) => {
+                state.waiting.wake_one();
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        with_lock(&self.state, |state| state.queue.len())
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let queue = with_lock(&self.state, |state| {
+            state.receiver_alive = false;
+            state.receiver = None;
+            state.waiting.wake_all();
+            core::mem::take(&mut state.queue)
+        });
+        // what was never received is dropped with interrupts enabled
+        drop(queue);
+    }
+}
+
+pub struct Recv<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        with_lock(&this.receiver.state, |state| {
+            if let Some(value) = state.queue.pop_front() {
+                state.waiting.wake_one();
+                return Poll::Ready(Some(value));
+            }
+            if state.senders == 0 {
+                return Poll::Ready(None);
+            }
+
+            state.receiver = Some(context.waker().clone());
+            Poll::Pending
+        })
+    }
+}
+
+// a sender must not wake a task that stopped waiting, it may be gone by then
+impl<T> Drop for Recv<'_, T> {
+    fn drop(&mut self) {
+        let waker = with_lock(&self.receiver.state, |state| state.receiver.take());
+        // dropped with interrupts enabled
+        drop(waker);
+    }
+}
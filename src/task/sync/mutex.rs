@@ -0,0 +1,139 @@
+/**************************************************************************************************
+* Name : 								  task/sync/mutex.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 				  A Lock Tasks Wait for instead of Spinning
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{with_lock, WaitList};
+use crate::lock;
+use core::{cell::UnsafeCell, future::Future, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
+
+struct State {
+    locked: bool,
+    waiters: WaitList,
+}
+
+impl State {
+    // to the oldest waiter if there is one, otherwise it is unlocked
+    fn hand_over(&mut self) {
+        if !self.waiters.wake_one() {
+            self.locked = false;
+        }
+    }
+}
+
+/*********************************************************
+* Like spin::Mutex, but a task that finds it locked
+	returns Pending and the executor runs something else.
+	The guard can be held across awaits. Waiting tasks get
+	it in the order they came: unlocking hands it straight
+	to the oldest one, it stays locked in between.
+*********************************************************/
+pub struct Mutex<T> {
+    state: lock::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            state: lock::Mutex::new(State { locked: false, waiters: WaitList::new() }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock { mutex: self, key: None, acquired: false }
+    }
+
+    // the lock right away, unless it is held or others are waiting for it
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        with_lock(&self.state, |state| {
+            if state.locked || !state.waiters.is_empty() {
+                return None;
+            }
+            state.locked = true;
+            Some(MutexGuard { mutex: self })
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn unlock(&self) {
+        with_lock(&self.state, State::hand_over);
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+pub struct Lock<'a, T> {
+    mutex: &'a Mutex<T>,
+    key: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<MutexGuard<'a, T>> {
+        let this = self.get_mut();
+        let mutex = this.mutex;
+        with_lock(&mutex.state, |state| {
+            // woken means the lock was handed over, it is ours already
+            let handed_over = state.waiters.was_woken(this.key);
+            if handed_over || (this.key.is_none() && !state.locked && state.waiters.is_empty()) {
+                state.locked = true;
+                this.acquired = true;
+                return Poll::Ready(MutexGuard { mutex });
+            }
+
+            state.waiters.register(&mut this.key, context.waker());
+            Poll::Pending
+        })
+    }
+}
+
+// handed a lock it won't take, the next waiter gets it
+impl<T> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        let key = match self.key {
+            Some(key) if !self.acquired => key,
+            _ => return,
+        };
+
+        with_lock(&self.mutex.state, |state| {
+            if !state.waiters.remove(key) {
+                state.hand_over();
+            }
+        });
+    }
+}
@@ -0,0 +1,96 @@
+/**************************************************************************************************
+* Name : 								 task/sync/notify.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					 Waking Tasks that Wait for an Event
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{with_lock, WaitList};
+use crate::lock;
+use core::{future::Future, pin::Pin, task::{Context, Poll}};
+
+struct State {
+    // a notify_one that came while nobody was waiting
+    permit: bool,
+    waiters: WaitList,
+}
+
+/*********************************************************
+* Lets tasks wait until something tells them to go on.
+	A `notify_one` with nobody waiting is kept for the
+	next task that waits, so it can't be missed.
+*********************************************************/
+pub struct Notify {
+    state: lock::Mutex<State>,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Notify {
+            state: lock::Mutex::new(State { permit: false, waiters: WaitList::new() }),
+        }
+    }
+
+    // wakes the task that waited longest, safe in interrupt handlers
+    pub fn notify_one(&self) {
+        with_lock(&self.state, |state| {
+            if !state.waiters.wake_one() {
+                state.permit = true;
+            }
+        });
+    }
+
+    // wakes everyone waiting right now, nothing is kept for later
+    pub fn notify_waiters(&self) {
+        with_lock(&self.state, |state| state.waiters.wake_all());
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        Notified { notify: self, key: None, done: false }
+    }
+}
+
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    key: Option<u64>,
+    done: bool,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        let this = self.get_mut();
+        with_lock(&this.notify.state, |state| {
+            if state.waiters.was_woken(this.key) {
+                this.done = true;
+                return Poll::Ready(());
+            }
+            if this.key.is_none() && state.permit {
+                state.permit = false;
+                this.done = true;
+                return Poll::Ready(());
+            }
+
+            state.waiters.register(&mut this.key, context.waker());
+            Poll::Pending
+        })
+    }
+}
+
+// woken but dropped before it saw it, the wakeup goes to the next one
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        let key = match self.key {
+            Some(key) if !self.done => key,
+            _ => return,
+        };
+
+        with_lock(&self.notify.state, |state| {
+            if !state.waiters.remove(key) && !state.waiters.wake_one() {
+                state.permit = true;
+            }
+        });
+    }
+}
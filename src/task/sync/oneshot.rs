@@ -0,0 +1,145 @@
+/**************************************************************************************************
+* Name : 							    task/sync/oneshot.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					   A Channel for Exactly One Value
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::with_lock;
+use crate::lock;
+use alloc::sync::Arc;
+use core::{fmt, future::Future, pin::Pin, task::{Context, Poll, Waker}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    // the sender was dropped without sending
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecvError::Closed => write!(f, "sender dropped without sending"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+}
+
+struct State<T> {
+    value: Option<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    waker: Option<Waker>,
+}
+
+type Shared<T> = Arc<lock::Mutex<State<T>>>;
+
+/*********************************************************
+* A sender and a receiver for a single value. Both are
+	allocated here, sending doesn't allocate and works in
+	interrupt handlers.
+*********************************************************/
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let state = Arc::new(lock::Mutex::new(State {
+        value: None,
+        sender_alive: true,
+        receiver_alive: true,
+        waker: None,
+    }));
+    (Sender { state: state.clone() }, Receiver { state, done: false })
+}
+
+pub struct Sender<T> {
+    state: Shared<T>,
+}
+
+impl<T> Sender<T> {
+    // gives the value back if the receiver is gone
+    pub fn send(self, value: T) -> Result<(), T> {
+        with_lock(&self.state, |state| {
+            if !state.receiver_alive {
+                return Err(value);
+            }
+            state.value = Some(value);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+            Ok(())
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        with_lock(&self.state, |state| !state.receiver_alive)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        with_lock(&self.state, |state| {
+            state.sender_alive = false;
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+    }
+}
+
+/*********************************************************
+* Resolves to the value once it is sent, or to an error
+	if the sender goes away first
+*********************************************************/
+pub struct Receiver<T> {
+    state: Shared<T>,
+    done: bool,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        with_lock(&self.state, |state| match state.value.take() {
+            Some(value) => Ok(value),
+            None if state.sender_alive => Err(TryRecvError::Empty),
+            None => Err(TryRecvError::Closed),
+        })
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T, RecvError>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        assert!(!this.done, "oneshot::Receiver polled after it returned");
+
+        let poll = with_lock(&this.state, |state| {
+            if let Some(value) = state.value.take() {
+                return Poll::Ready(Ok(value));
+            }
+            if !state.sender_alive {
+                return Poll::Ready(Err(RecvError::Closed));
+            }
+
+            state.waker = Some(context.waker().clone());
+            Poll::Pending
+        });
+        this.done = poll.is_ready();
+        poll
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let value = with_lock(&self.state, |state| {
+            state.receiver_alive = false;
+            state.waker = None;
+            state.value.take()
+        });
+        // dropped with interrupts enabled
+        drop(value);
+    }
+}
@@ -0,0 +1,230 @@
+/**************************************************************************************************
+* Name : 								 task/sync/rwlock.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 			    Many Readers or One Writer, Waited for Async
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{with_lock, WaitList};
+use crate::lock;
+use core::{cell::UnsafeCell, future::Future, ops::{Deref, DerefMut}, pin::Pin, task::{Context, Poll}};
+
+struct State {
+    readers: usize,
+    writer: bool,
+    waiting_readers: WaitList,
+    waiting_writers: WaitList,
+}
+
+impl State {
+    /*****************************************************
+    * Once a writer is done the readers waiting meanwhile
+    	all go, after the last reader the oldest writer.
+    	Readers only go first if a writer just had it.
+    *****************************************************/
+    fn wake_next(&mut self, readers_first: bool) {
+        if self.writer || self.readers > 0 {
+            return;
+        }
+        if readers_first && !self.waiting_readers.is_empty() {
+            self.waiting_readers.wake_all();
+        } else if !self.waiting_writers.wake_one() {
+            self.waiting_readers.wake_all();
+        }
+    }
+}
+
+/*********************************************************
+* Any number of tasks can read at once, a writer has it
+	alone. New readers wait while a writer is waiting, so
+	writers aren't starved by a stream of readers.
+*********************************************************/
+pub struct RwLock<T> {
+    state: lock::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        RwLock {
+            state: lock::Mutex::new(State {
+                readers: 0,
+                writer: false,
+                waiting_readers: WaitList::new(),
+                waiting_writers: WaitList::new(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> Read<'_, T> {
+        Read { lock: self, key: None, acquired: false }
+    }
+
+    pub fn write(&self) -> Write<'_, T> {
+        Write { lock: self, key: None, acquired: false }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        with_lock(&self.state, |state| {
+            if state.writer || !state.waiting_writers.is_empty() {
+                return None;
+            }
+            state.readers += 1;
+            Some(RwLockReadGuard { lock: self })
+        })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        with_lock(&self.state, |state| {
+            if state.writer || state.readers > 0 || !state.waiting_writers.is_empty() {
+                return None;
+            }
+            state.writer = true;
+            Some(RwLockWriteGuard { lock: self })
+        })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        with_lock(&self.lock.state, |state| {
+            state.readers -= 1;
+            state.wake_next(false);
+        });
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        with_lock(&self.lock.state, |state| {
+            state.writer = false;
+            state.wake_next(true);
+        });
+    }
+}
+
+pub struct Read<'a, T> {
+    lock: &'a RwLock<T>,
+    key: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T> Future for Read<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<RwLockReadGuard<'a, T>> {
+        let this = self.get_mut();
+        let lock = this.lock;
+        with_lock(&lock.state, |state| {
+            // woken readers go even if a writer has come along since
+            let woken = state.waiting_readers.was_woken(this.key);
+            if !state.writer && (woken || state.waiting_writers.is_empty()) {
+                state.readers += 1;
+                this.acquired = true;
+                return Poll::Ready(RwLockReadGuard { lock });
+            }
+
+            if woken {
+                this.key = None;
+            }
+            state.waiting_readers.register(&mut this.key, context.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T> Drop for Read<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.filter(|_| !self.acquired) {
+            with_lock(&self.lock.state, |state| {
+                state.waiting_readers.remove(key);
+                // the other readers woken with it don't need this one
+                state.wake_next(false);
+            });
+        }
+    }
+}
+
+pub struct Write<'a, T> {
+    lock: &'a RwLock<T>,
+    key: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a, T> Future for Write<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<RwLockWriteGuard<'a, T>> {
+        let this = self.get_mut();
+        let lock = this.lock;
+        with_lock(&lock.state, |state| {
+            let turn = state.waiting_writers.was_woken(this.key) || state.waiting_writers.is_empty();
+            if turn && !state.writer && state.readers == 0 {
+                state.writer = true;
+                this.acquired = true;
+                return Poll::Ready(RwLockWriteGuard { lock });
+            }
+
+            if state.waiting_writers.was_woken(this.key) {
+                this.key = None;
+            }
+            state.waiting_writers.register(&mut this.key, context.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<T> Drop for Write<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.filter(|_| !self.acquired) {
+            with_lock(&self.lock.state, |state| {
+                state.waiting_writers.remove(key);
+                // readers may have waited only because of this writer
+                if !state.writer && state.waiting_writers.is_empty() {
+                    state.waiting_readers.wake_all();
+                } else {
+                    state.wake_next(false);
+                }
+            });
+        }
+    }
+}
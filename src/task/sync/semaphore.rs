@@ -0,0 +1,135 @@
+/**************************************************************************************************
+* Name : 							    task/sync/semaphore.rs
+* Author : 										Avery
+* Date : 									  10/19/2026
+* Purpose : 					  Counting Permits Tasks Wait to Get
+* Version : 									 0.1
+**************************************************************************************************/
+
+use super::{with_lock, WaitList};
+use crate::lock;
+use core::{future::Future, mem, pin::Pin, task::{Context, Poll}};
+
+struct State {
+    permits: usize,
+    waiters: WaitList,
+}
+
+impl State {
+    // each permit goes straight to the oldest waiter, the rest are kept
+    fn release(&mut self, count: usize) {
+        for given in 0..count {
+            if !self.waiters.wake_one() {
+                self.permits += count - given;
+                return;
+            }
+        }
+    }
+}
+
+/*********************************************************
+* A number of permits, a task that wants one while there
+	are none waits until one is given back. Waiting tasks
+	get them in the order they came: a permit given back
+	goes straight to the oldest one.
+*********************************************************/
+pub struct Semaphore {
+    state: lock::Mutex<State>,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: lock::Mutex::new(State { permits, waiters: WaitList::new() }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        with_lock(&self.state, |state| state.permits)
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire { semaphore: self, key: None, acquired: false }
+    }
+
+    // a permit right away, unless there is none or others are waiting for one
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        with_lock(&self.state, |state| {
+            if state.permits == 0 || !state.waiters.is_empty() {
+                return None;
+            }
+            state.permits -= 1;
+            Some(SemaphorePermit { semaphore: self })
+        })
+    }
+
+    // safe in interrupt handlers, wakes as many waiters as there are new permits
+    pub fn add_permits(&self, count: usize) {
+        with_lock(&self.state, |state| state.release(count));
+    }
+}
+
+/*********************************************************
+* Gives its permit back when dropped, `forget` keeps it
+	taken for good
+*********************************************************/
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl SemaphorePermit<'_> {
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
+
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    key: Option<u64>,
+    acquired: bool,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<SemaphorePermit<'a>> {
+        let this = self.get_mut();
+        let semaphore = this.semaphore;
+        with_lock(&semaphore.state, |state| {
+            // woken means a permit was handed over, it is ours already
+            let handed_over = state.waiters.was_woken(this.key);
+            if !handed_over && (this.key.is_some() || state.permits == 0 || !state.waiters.is_empty()) {
+                state.waiters.register(&mut this.key, context.waker());
+                return Poll::Pending;
+            }
+
+            if !handed_over {
+                state.permits -= 1;
+            }
+            this.acquired = true;
+            Poll::Ready(SemaphorePermit { semaphore })
+        })
+    }
+}
+
+// handed a permit it won't take, the next waiter gets it
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        let key = match self.key {
+            Some(key) if !self.acquired => key,
+            _ => return,
+        };
+
+        with_lock(&self.semaphore.state, |state| {
+            if !state.waiters.remove(key) {
+                state.release(1);
+            }
+        });
+    }
+}
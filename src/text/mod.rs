@@ -11,7 +11,7 @@ use core::fmt::{self, Write};
 use alloc::{string::{ToString, String}, format, vec::Vec};
 use midas_vga::text::{write_string, write_char};
 use vga::{colors::{TextModeColor, Color16}, writers::{Text80x25, TextWriter, ScreenCharacter}};
-use spin::Mutex;
+use crate::lock::Mutex;
 use lazy_static::lazy_static;
 use volatile::Volatile;
 
@@ -12,7 +12,7 @@ use midas_vga::graphics::{draw_shape, write_str_centered_x, to_usize};
 use midas_vga::shapes::*;
 use midas_vga::math::calculate_centered_rect;
 use pc_keyboard::{DecodedKey, KeyCode};
-use spin::Mutex;
+use crate::lock::Mutex;
 use vga::{colors::{Color16}, writers::{Graphics640x480x16, GraphicsWriter, Text80x25, TextWriter}, drawing::Point};
 use lazy_static::lazy_static;
 
@@ -0,0 +1,8 @@
+#!/bin/sh
+# Runs the heap tests against every allocator the kernel can be built with
+set -e
+
+for allocator in fixed-size-block bump linked-list slab; do
+    echo "heap_allocation with alloc-$allocator"
+    cargo test --test heap_allocation --no-default-features --features "alloc-$allocator" "$@"
+done
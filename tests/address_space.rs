@@ -0,0 +1,226 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, fault::{self, FaultKind}, memory::{self, address_space::{self, AddressSpace, USER_SPACE_START}, mapping::{self, MapError, MapFlags}}};
+use core::panic::PanicInfo;
+use x86_64::{structures::paging::Translate, VirtAddr};
+
+entry_point!(main);
+
+// a level 4 entry nothing else uses
+const LATE_KERNEL_AREA: u64 = 0x_7000_0000_0000;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+fn free_frames() -> usize {
+    memory::frame_stats().unwrap().free_frames
+}
+
+#[test_case]
+fn kernel_mappings_are_shared() {
+    let space = AddressSpace::new().expect("no address space");
+    let value = Box::new(41u64);
+    let addr = VirtAddr::from_ptr(&*value);
+
+    let kernel = unsafe { memory::kernel_mapper() }.translate_addr(addr);
+    assert!(kernel.is_some());
+    assert_eq!(space.translate(addr), kernel);
+}
+
+#[test_case]
+fn user_mappings_are_private() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let first = AddressSpace::new().expect("no address space");
+    let second = AddressSpace::new().expect("no address space");
+
+    first.map(start, 4096, MapFlags::KERNEL_DATA).expect("mapping failed");
+    second.map(start, 4096, MapFlags::KERNEL_DATA).expect("mapping failed");
+    first.copy_to(start, b"first").unwrap();
+    second.copy_to(start, b"second").unwrap();
+
+    assert_ne!(first.translate(start), second.translate(start));
+    assert_eq!(unsafe { memory::kernel_mapper() }.translate_addr(start), None);
+
+    let mut buffer = [0u8; 6];
+    first.copy_from(start, &mut buffer[..5]).unwrap();
+    assert_eq!(&buffer[..5], b"first");
+    second.copy_from(start, &mut buffer).unwrap();
+    assert_eq!(&buffer, b"second");
+}
+
+#[test_case]
+fn switching_loads_the_address_space() {
+    let start = VirtAddr::new(USER_SPACE_START + 0x1000);
+    let first = Arc::new(AddressSpace::new().expect("no address space"));
+    let second = Arc::new(AddressSpace::new().expect("no address space"));
+    first.map(start, 4096, MapFlags::KERNEL_DATA).unwrap();
+    second.map(start, 4096, MapFlags::KERNEL_DATA).unwrap();
+    first.copy_to(start, &[1]).unwrap();
+    second.copy_to(start, &[2]).unwrap();
+
+    address_space::switch_to(Some(&first));
+    assert!(first.is_active());
+    assert_eq!(unsafe { start.as_ptr::<u8>().read_volatile() }, 1);
+
+    address_space::switch_to(Some(&second));
+    assert!(!first.is_active());
+    assert_eq!(unsafe { start.as_ptr::<u8>().read_volatile() }, 2);
+
+    address_space::switch_to(None);
+    assert!(!second.is_active());
+    assert!(address_space::current().is_none());
+}
+
+#[test_case]
+fn teardown_frees_every_frame() {
+    let before = free_frames();
+    {
+        let space = AddressSpace::new().expect("no address space");
+        space.map(VirtAddr::new(USER_SPACE_START), 16 * 4096, MapFlags::KERNEL_DATA).unwrap();
+        // far enough away to need tables of its own
+        space.map(VirtAddr::new(USER_SPACE_START + 0x80_0000_0000), 4096, MapFlags::KERNEL_DATA).unwrap();
+        assert_eq!(space.resident_pages(), 17);
+        assert!(free_frames() < before);
+    }
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn unmapping_frees_frames() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let space = AddressSpace::new().expect("no address space");
+    space.map(start, 4 * 4096, MapFlags::KERNEL_DATA).unwrap();
+    let mapped = free_frames();
+
+    space.unmap(start, 4 * 4096).unwrap();
+    assert_eq!(free_frames(), mapped + 4);
+    assert_eq!(space.resident_pages(), 0);
+    assert_eq!(space.translate(start), None);
+    assert_eq!(space.unmap(start, 4096), Err(MapError::NotMapped(start)));
+}
+
+#[test_case]
+fn only_user_space_can_be_mapped() {
+    let space = AddressSpace::new().expect("no address space");
+    let kernel = VirtAddr::new(LATE_KERNEL_AREA);
+    let straddling = VirtAddr::new(USER_SPACE_START - 4096);
+
+    assert_eq!(space.map(kernel, 4096, MapFlags::KERNEL_DATA), Err(MapError::OutsideUserSpace(kernel)));
+    assert_eq!(space.map(straddling, 2 * 4096, MapFlags::KERNEL_DATA), Err(MapError::OutsideUserSpace(straddling)));
+    assert_eq!(space.resident_pages(), 0);
+}
+
+#[test_case]
+fn late_kernel_mappings_show_up() {
+    let space = Arc::new(AddressSpace::new().expect("no address space"));
+    address_space::switch_to(Some(&space));
+
+    // a new level 4 entry in the kernel's table, the page fault handler syncs it
+    let start = VirtAddr::new(LATE_KERNEL_AREA);
+    mapping::map_range(start, 4096, MapFlags::KERNEL_DATA).unwrap();
+    unsafe { start.as_mut_ptr::<u64>().write_volatile(0x1234) };
+    assert_eq!(unsafe { start.as_ptr::<u64>().read_volatile() }, 0x1234);
+
+    address_space::switch_to(None);
+    mapping::unmap_range(start, 4096, true).unwrap();
+}
+
+#[test_case]
+fn reserved_pages_are_backed_on_first_touch() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let space = Arc::new(AddressSpace::new().expect("no address space"));
+    space.reserve(start, 16 * 4096, MapFlags::KERNEL_DATA).unwrap();
+    assert_eq!(space.resident_pages(), 0);
+    assert_eq!(space.translate(start), None);
+
+    address_space::switch_to(Some(&space));
+    assert_eq!(unsafe { start.as_ptr::<u64>().read_volatile() }, 0);
+    let last = start + 15 * 4096u64;
+    unsafe { last.as_mut_ptr::<u64>().write_volatile(7) };
+    address_space::switch_to(None);
+
+    assert_eq!(space.resident_pages(), 2);
+    let mut value = [0u8; 1];
+    space.copy_from(last, &mut value).unwrap();
+    assert_eq!(value, [7]);
+}
+
+#[test_case]
+fn forks_copy_pages_on_write() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let before = free_frames();
+    {
+        let parent = Arc::new(AddressSpace::new().expect("no address space"));
+        parent.map(start, 2 * 4096, MapFlags::KERNEL_DATA).unwrap();
+        parent.copy_to(start, b"parent").unwrap();
+
+        let child = Arc::new(parent.fork().expect("fork failed"));
+        assert_eq!(child.areas(), parent.areas());
+        assert_eq!(child.translate(start), parent.translate(start));
+
+        address_space::switch_to(Some(&child));
+        unsafe { start.as_mut_ptr::<u8>().write_volatile(b'c') };
+        address_space::switch_to(None);
+
+        assert_ne!(child.translate(start), parent.translate(start));
+        // the second page wasn't written, it is still shared
+        assert_eq!(child.translate(start + 4096u64), parent.translate(start + 4096u64));
+
+        let mut buffer = [0u8; 6];
+        parent.copy_from(start, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"parent");
+        child.copy_from(start, &mut buffer).unwrap();
+        assert_eq!(&buffer, b"carent");
+
+        // the parent is the only one left on the second page, so it keeps it
+        let shared = parent.translate(start + 4096u64);
+        drop(child);
+        parent.copy_to(start + 4096u64, &[1]).unwrap();
+        assert_eq!(parent.translate(start + 4096u64), shared);
+    }
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn invalid_accesses_still_fault() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let read_only = MapFlags { writable: false, ..MapFlags::KERNEL_DATA };
+    let space = Arc::new(AddressSpace::new().expect("no address space"));
+    space.reserve(start, 4096, read_only).unwrap();
+
+    address_space::switch_to(Some(&space));
+    let outside = fault::catch(|| unsafe { (start + 4096u64).as_ptr::<u8>().read_volatile(); });
+    let write = fault::catch(|| unsafe { start.as_mut_ptr::<u8>().write_volatile(1) });
+    let read = fault::catch(|| unsafe { start.as_ptr::<u8>().read_volatile(); });
+    address_space::switch_to(None);
+
+    assert_eq!(outside.unwrap_err().kind, FaultKind::PageFault);
+    assert_eq!(write.unwrap_err().kind, FaultKind::PageFault);
+    assert!(read.is_ok());
+    assert_eq!(space.copy_to(start, &[1]), Err(MapError::NotWritable(start)));
+}
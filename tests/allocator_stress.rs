@@ -0,0 +1,180 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{alloc::{GlobalAlloc, Layout}, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use midas::allocator::{self, Locked, linked_list::{FitStrategy, LinkedListAllocator}};
+use core::{panic::PanicInfo, ptr};
+
+entry_point!(main);
+
+const ARENA_SIZE: usize = 64 * 1024;
+
+#[repr(C, align(4096))]
+struct Arena([u8; ARENA_SIZE]);
+
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::{self, GlobalFrameAllocator};
+    use x86_64::VirtAddr;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+/*********************************************
+* Every test gets a fresh allocator over the
+	whole arena
+*********************************************/
+fn arena_allocator(strategy: FitStrategy) -> Locked<LinkedListAllocator> {
+    let allocator = Locked::new(LinkedListAllocator::with_strategy(strategy));
+    unsafe {
+        allocator.lock().init(ptr::addr_of_mut!(ARENA) as usize, ARENA_SIZE);
+    }
+    allocator
+}
+
+// small xorshift generator so the tests don't depend on the rdrand/rdseed hardware
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[test_case]
+fn freed_regions_coalesce() {
+    let allocator = arena_allocator(FitStrategy::FirstFit);
+    let layout = Layout::from_size_align(512, 8).unwrap();
+
+    let blocks: Vec<*mut u8> = (0..64)
+        .map(|_| unsafe { allocator.alloc(layout) })
+        .collect();
+    assert!(blocks.iter().all(|block| !block.is_null()));
+
+    // free every other block first so each free has to merge on both sides later
+    for block in blocks.iter().step_by(2).chain(blocks.iter().skip(1).step_by(2)) {
+        unsafe { allocator.dealloc(*block, layout) };
+    }
+
+    assert_eq!(allocator.lock().free_regions(), 1);
+    assert_eq!(allocator.lock().free_size(), ARENA_SIZE);
+
+    let whole = Layout::from_size_align(ARENA_SIZE, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(whole) };
+    assert!(!ptr.is_null());
+    unsafe { allocator.dealloc(ptr, whole) };
+}
+
+/*********************************************
+* Leaves a 256 and a 64 byte hole and returns
+	where a 64 byte allocation ends up
+*********************************************/
+fn fit_into_holes(strategy: FitStrategy) -> (*mut u8, *mut u8, *mut u8) {
+    let allocator = arena_allocator(strategy);
+    let big = Layout::from_size_align(256, 8).unwrap();
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let guard = Layout::from_size_align(32, 8).unwrap();
+
+    unsafe {
+        let big_hole = allocator.alloc(big);
+        allocator.alloc(guard);
+        let small_hole = allocator.alloc(small);
+        allocator.alloc(guard);
+
+        allocator.dealloc(big_hole, big);
+        allocator.dealloc(small_hole, small);
+
+        (allocator.alloc(small), big_hole, small_hole)
+    }
+}
+
+#[test_case]
+fn first_fit_takes_lowest_hole() {
+    let (ptr, big_hole, _) = fit_into_holes(FitStrategy::FirstFit);
+    assert_eq!(ptr, big_hole);
+}
+
+#[test_case]
+fn best_fit_takes_smallest_hole() {
+    let (ptr, _, small_hole) = fit_into_holes(FitStrategy::BestFit);
+    assert_eq!(ptr, small_hole);
+}
+
+fn random_stress(strategy: FitStrategy, seed: u64) {
+    let allocator = arena_allocator(strategy);
+    let mut rng = Rng(seed);
+    let mut live: Vec<Option<(*mut u8, Layout, u8)>> = (0..48).map(|_| None).collect();
+
+    for round in 0..4000 {
+        let slot = rng.below(live.len());
+        match live[slot].take() {
+            Some((ptr, layout, tag)) => unsafe {
+                let contents = core::slice::from_raw_parts(ptr, layout.size());
+                assert!(contents.iter().all(|byte| *byte == tag), "allocation was overwritten");
+                allocator.dealloc(ptr, layout);
+            },
+            None => {
+                let size = 1 + rng.below(2048);
+                let align = 1 << rng.below(9);
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { allocator.alloc(layout) };
+
+                // the arena can run out with this many live allocations, that's fine
+                if ptr.is_null() {
+                    continue;
+                }
+                assert_eq!(ptr as usize % align, 0);
+
+                let tag = round as u8;
+                unsafe { ptr::write_bytes(ptr, tag, size) };
+                live[slot] = Some((ptr, layout, tag));
+            }
+        }
+    }
+
+    for (ptr, layout, _) in live.into_iter().flatten() {
+        unsafe { allocator.dealloc(ptr, layout) };
+    }
+
+    // nothing leaked and everything merged back together
+    assert_eq!(allocator.lock().free_regions(), 1);
+    assert_eq!(allocator.lock().free_size(), ARENA_SIZE);
+}
+
+#[test_case]
+fn random_stress_first_fit() {
+    random_stress(FitStrategy::FirstFit, 0x2545_f491_4f6c_dd1d);
+}
+
+#[test_case]
+fn random_stress_best_fit() {
+    random_stress(FitStrategy::BestFit, 0x9e37_79b9_7f4a_7c15);
+}
@@ -0,0 +1,80 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory, task::{deferred::{self, Work}, executor::Executor, Task}};
+use core::{panic::PanicInfo, sync::atomic::{AtomicU64, Ordering}};
+use x86_64::{instructions::interrupts, VirtAddr};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+
+fn add(data: u64) {
+    TOTAL.fetch_add(data, Ordering::SeqCst);
+}
+
+#[test_case]
+fn scheduling_before_init_gives_the_work_back() {
+    let dropped = deferred::stats().dropped;
+    assert!(deferred::schedule(Work::new(add, 1)).is_err());
+    assert_eq!(deferred::stats().dropped, dropped + 1);
+}
+
+#[test_case]
+fn work_runs_on_the_executor() {
+    deferred::init();
+    let mut executor = Executor::new();
+    executor.spawn(Task::named("deferred", deferred::run()));
+
+    // queued with interrupts off, the way a handler would
+    interrupts::without_interrupts(|| {
+        for value in 1..=10 {
+            deferred::schedule(Work::new(add, value)).unwrap();
+        }
+    });
+    assert_eq!(TOTAL.load(Ordering::SeqCst), 0);
+
+    let completed = deferred::stats().completed;
+    executor.run_until_idle();
+    assert_eq!(TOTAL.load(Ordering::SeqCst), 55);
+    assert_eq!(deferred::stats().completed, completed + 10);
+}
+
+#[test_case]
+fn a_full_queue_gives_the_work_back() {
+    let mut queued = 0;
+    while deferred::schedule(Work::new(add, 0)).is_ok() {
+        queued += 1;
+        assert!(queued <= 1024, "the work queue never fills up");
+    }
+
+    let stats = deferred::stats();
+    assert!(deferred::schedule(Work::new(add, 0)).is_err());
+    assert_eq!(deferred::stats().dropped, stats.dropped + 1);
+}
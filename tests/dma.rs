@@ -0,0 +1,115 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory::{self, dma::{CacheMode, DmaBuffer}}};
+use core::panic::PanicInfo;
+use x86_64::{registers::model_specific::Msr, structures::paging::{PageTableFlags, Translate, mapper::TranslateResult}, VirtAddr};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+fn page_flags(addr: VirtAddr) -> PageTableFlags {
+    match unsafe { memory::kernel_mapper() }.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => flags,
+        _ => panic!("{:?} is not mapped", addr),
+    }
+}
+
+#[test_case]
+fn buffers_are_contiguous_and_zeroed() {
+    let size = 5 * 4096 + 100;
+    let buffer = DmaBuffer::new(size, CacheMode::Uncached).expect("no DMA buffer");
+    let mapper = unsafe { memory::kernel_mapper() };
+
+    assert_eq!(buffer.len(), size);
+    for page in 0..6u64 {
+        let phys = mapper.translate_addr(buffer.virt_addr() + page * 4096);
+        assert_eq!(phys, Some(buffer.phys_addr() + page * 4096));
+    }
+
+    let mut bytes = [0xffu8; 64];
+    buffer.read_bytes(size - 64, &mut bytes).unwrap();
+    assert!(bytes.iter().all(|&byte| byte == 0));
+}
+
+#[test_case]
+fn buffers_can_stay_below_4gib() {
+    let buffer = DmaBuffer::new_below_4gib(16 * 4096, CacheMode::Uncached).expect("no DMA buffer");
+    assert!(buffer.phys_addr().as_u64() + 16 * 4096 <= 0x_1_0000_0000);
+}
+
+#[test_case]
+fn cache_modes_pick_the_page_attributes() {
+    let uncached = DmaBuffer::new(4096, CacheMode::Uncached).expect("no DMA buffer");
+    let flags = page_flags(uncached.virt_addr());
+    assert!(flags.contains(PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH));
+
+    let combining = DmaBuffer::new(4096, CacheMode::WriteCombining).expect("no DMA buffer");
+    let flags = page_flags(combining.virt_addr());
+    match combining.cache_mode() {
+        CacheMode::WriteCombining => {
+            // the PAT bit of a 4 KiB entry, with PWT and PCD clear
+            assert!(flags.contains(PageTableFlags::HUGE_PAGE));
+            assert!(!flags.intersects(PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE));
+
+            // write-through only mappings still get write-through
+            let pat = unsafe { Msr::new(0x277).read() };
+            assert_eq!(pat >> 8 & 0xff, 0x04);
+            assert_eq!(pat >> 32 & 0xff, 0x01);
+        }
+        CacheMode::Uncached => assert!(flags.contains(PageTableFlags::NO_CACHE)),
+    }
+}
+
+#[test_case]
+fn data_reaches_physical_memory() {
+    let mut buffer = DmaBuffer::new(4096, CacheMode::WriteCombining).expect("no DMA buffer");
+    buffer.write::<u32>(8, 0xdead_beef).unwrap();
+    buffer.write_bytes(100, b"dma").unwrap();
+    assert!(buffer.write::<u64>(4092, 0).is_err());
+
+    // what a device would see at the physical address
+    let phys = memory::physical_memory_offset() + buffer.phys_addr().as_u64();
+    unsafe { core::arch::asm!("sfence") };
+    assert_eq!(unsafe { (phys + 8u64).as_ptr::<u32>().read_volatile() }, 0xdead_beef);
+    assert_eq!(buffer.read::<u32>(8).unwrap(), 0xdead_beef);
+}
+
+#[test_case]
+fn dropping_frees_the_frames() {
+    let before = memory::frame_stats().unwrap().free_frames;
+    let buffer = DmaBuffer::new(8 * 4096, CacheMode::Uncached).expect("no DMA buffer");
+    // page tables for the driver window may have come on top
+    assert!(memory::frame_stats().unwrap().free_frames <= before - 8);
+    let allocated = memory::frame_stats().unwrap().free_frames;
+
+    let virt = buffer.virt_addr();
+    drop(buffer);
+    assert_eq!(memory::frame_stats().unwrap().free_frames, allocated + 8);
+    assert_eq!(unsafe { memory::kernel_mapper() }.translate_addr(virt), None);
+}
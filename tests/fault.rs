@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, fault::{self, FaultKind}, lock::{self, Mutex}, memory};
+use core::{arch::asm, panic::PanicInfo};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn commands_without_faults_return_ok() {
+    let mut ran = false;
+    assert!(fault::catch(|| ran = true).is_ok());
+    assert!(ran);
+    assert!(!fault::is_active());
+}
+
+#[test_case]
+fn invalid_opcodes_are_recovered() {
+    let fault = fault::catch(|| unsafe { asm!("ud2") }).expect_err("ud2 didn't fault");
+    assert_eq!(fault.kind, FaultKind::InvalidOpcode);
+    assert!(!fault::is_active());
+}
+
+#[test_case]
+fn divide_errors_are_recovered() {
+    let fault = fault::catch(|| unsafe {
+        asm!("xor edx, edx", "xor ecx, ecx", "div ecx", out("eax") _, out("edx") _, out("ecx") _);
+    })
+    .expect_err("dividing by zero didn't fault");
+    assert_eq!(fault.kind, FaultKind::DivideError);
+}
+
+#[test_case]
+fn page_faults_report_the_address() {
+    let address = 0xdead_beef_000u64;
+    let fault = fault::catch(|| unsafe {
+        core::ptr::read_volatile(address as *const u64);
+    })
+    .expect_err("reading unmapped memory didn't fault");
+
+    assert_eq!(fault.kind, FaultKind::PageFault);
+    assert_eq!(fault.address, Some(VirtAddr::new(address)));
+}
+
+#[test_case]
+fn the_heap_still_works_after_a_fault() {
+    let _ = fault::catch(|| unsafe { asm!("ud2") });
+    let values: Vec<u64> = (0..100).collect();
+    assert_eq!(values.iter().sum::<u64>(), 4950);
+}
+
+#[test_case]
+fn nested_catches_use_the_outer_recovery_point() {
+    let mut inner = None;
+    let outer = fault::catch(|| {
+        inner = Some(fault::catch(|| ()));
+        unsafe { asm!("ud2") };
+    });
+
+    assert_eq!(inner.map(|result| result.is_ok()), Some(true));
+    assert_eq!(outer.map_err(|fault| fault.kind), Err(FaultKind::InvalidOpcode));
+}
+
+static OUTER: Mutex<u32> = Mutex::new(0);
+
+#[test_case]
+fn locks_taken_before_the_command_are_counted_but_allowed() {
+    let held = lock::held();
+    let guard = OUTER.lock();
+    assert_eq!(lock::held(), held + 1);
+
+    let fault = fault::catch(|| unsafe { asm!("ud2") }).expect_err("ud2 didn't fault");
+    assert_eq!(fault.kind, FaultKind::InvalidOpcode);
+
+    drop(guard);
+    assert_eq!(lock::held(), held);
+}
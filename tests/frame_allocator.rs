@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory::{self, GlobalFrameAllocator}};
+use core::panic::PanicInfo;
+use x86_64::{structures::paging::{FrameAllocator, FrameDeallocator}, PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+fn free_frames() -> usize {
+    memory::frame_stats().unwrap().free_frames
+}
+
+#[test_case]
+fn counters_add_up() {
+    let stats = memory::frame_stats().unwrap();
+    assert!(stats.total_frames > 0);
+    assert!(stats.free_frames > 0);
+    assert_eq!(stats.used_frames + stats.free_frames, stats.total_frames);
+}
+
+#[test_case]
+fn frames_come_back_after_freeing() {
+    let before = free_frames();
+    let frames: [_; 8] = core::array::from_fn(|_| GlobalFrameAllocator.allocate_frame().expect("out of frames"));
+    assert_eq!(free_frames(), before - 8);
+
+    for (index, frame) in frames.iter().enumerate() {
+        assert!(frames[..index].iter().all(|other| other != frame), "frame handed out twice");
+    }
+
+    for frame in frames {
+        unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+    }
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn contiguous_frames_are_aligned() {
+    let before = free_frames();
+    let range = memory::allocate_contiguous_frames(16, 16, None).expect("no 16 frames in a row");
+
+    assert_eq!(range.count(), 16);
+    assert_eq!(range.start.start_address().as_u64() % (16 * 4096), 0);
+    assert_eq!(free_frames(), before - 16);
+
+    unsafe { memory::deallocate_contiguous_frames(range) };
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn contiguous_frames_stay_below_the_limit() {
+    let limit = PhysAddr::new(16 * 1024 * 1024);
+    let range = memory::allocate_contiguous_frames(4, 1, Some(limit)).expect("no frames below 16 MiB");
+    assert!(range.end.start_address() <= limit);
+    unsafe { memory::deallocate_contiguous_frames(range) };
+
+    let before = free_frames();
+    assert!(memory::allocate_contiguous_frames(4, 1, Some(PhysAddr::new(4096))).is_none());
+    assert!(memory::allocate_contiguous_frames(0, 1, None).is_none());
+    assert!(memory::allocate_contiguous_frames(1, 0, None).is_none());
+    assert_eq!(free_frames(), before);
+}
+
+#[test_case]
+fn contiguous_frames_continue_after_the_last_run() {
+    let first = memory::allocate_contiguous_frames(4, 1, None).expect("no 4 frames in a row");
+    let second = memory::allocate_contiguous_frames(4, 1, None).expect("no 4 frames in a row");
+    assert!(second.start >= first.end);
+
+    unsafe {
+        memory::deallocate_contiguous_frames(first);
+        memory::deallocate_contiguous_frames(second);
+    }
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use midas::{memory::{self, GlobalFrameAllocator}, qemu::{exit_qemu, QemuExitCode}, serial_print, serial_println};
+use x86_64::{structures::paging::{FrameAllocator, FrameDeallocator}, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("frame_double_free::freeing_twice_panics...\t");
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+
+    let frame = GlobalFrameAllocator.allocate_frame().expect("out of frames");
+    unsafe {
+        GlobalFrameAllocator.deallocate_frame(frame);
+        GlobalFrameAllocator.deallocate_frame(frame);
+    }
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
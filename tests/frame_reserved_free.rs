@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{bootinfo::MemoryRegionType, entry_point, BootInfo};
+use core::panic::PanicInfo;
+use midas::{memory::{self, GlobalFrameAllocator}, qemu::{exit_qemu, QemuExitCode}, serial_print, serial_println};
+use x86_64::{structures::paging::{FrameDeallocator, PhysFrame}, PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("frame_reserved_free::freeing_reserved_memory_panics...\t");
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+
+    // the kernel's own frames are in use but were never handed out
+    let kernel = boot_info.memory_map.iter()
+        .find(|region| region.region_type == MemoryRegionType::Kernel)
+        .expect("no kernel region in the memory map");
+    let frame = PhysFrame::containing_address(PhysAddr::new(kernel.range.start_addr()));
+    unsafe { GlobalFrameAllocator.deallocate_frame(frame) };
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    loop {}
+}
@@ -6,9 +6,9 @@
 
 extern crate alloc;
 
-use alloc::{boxed::Box, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use bootloader::{entry_point, BootInfo};
-use midas::allocator::HEAP_SIZE;
+use midas::allocator::{self, HEAP_SIZE};
 use core::panic::PanicInfo;
 
 entry_point!(main);
@@ -19,16 +19,14 @@ fn panic(info: &PanicInfo) -> ! {
 }
 
 fn main(boot_info: &'static BootInfo) -> ! {
-    use midas::allocator;
-    use midas::memory::{self, BootInfoFrameAllocator};
+    use midas::memory::{self, GlobalFrameAllocator};
     use x86_64::VirtAddr;
 
     midas::init();
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe {
-        BootInfoFrameAllocator::init(&boot_info.memory_map)
-    };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
     allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
 
@@ -37,6 +35,22 @@ fn main(boot_info: &'static BootInfo) -> ! {
 }
 
 
+#[test_case]
+fn selected_allocator_is_global() {
+    let expected = if cfg!(feature = "alloc-bump") {
+        "bump"
+    } else if cfg!(feature = "alloc-linked-list") {
+        "linked list"
+    } else if cfg!(feature = "alloc-slab") {
+        "slab"
+    } else if cfg!(feature = "alloc-fixed-size-block") {
+        "fixed size block"
+    } else {
+        unreachable!("no allocator feature is enabled")
+    };
+    assert_eq!(allocator::allocator_stats().name, expected);
+}
+
 #[test_case]
 fn simple_allocation() {
     let heap_value_1 = Box::new(41);
@@ -72,4 +86,21 @@ fn many_boxes_long_lived() {
         assert_eq!(*x, i);
     }
     assert_eq!(*long_lived, 1);
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn heap_grows_past_initial_size() {
+    let initial_size = allocator::heap_stats().size;
+    let vec = vec![7u8; HEAP_SIZE * 2];
+    assert_eq!(vec.iter().map(|&x| x as usize).sum::<usize>(), HEAP_SIZE * 2 * 7);
+    assert!(allocator::heap_stats().size > initial_size);
+}
+
+#[test_case]
+fn fallible_allocation_fails_gracefully() {
+    let too_big = allocator::try_vec::<u8>(allocator::HEAP_MAX_SIZE * 2);
+    assert!(too_big.is_err());
+
+    let fits = allocator::try_box(42u64).expect("small allocation failed");
+    assert_eq!(*fits, 42);
+}
@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use bootloader::{entry_point, BootInfo};
+use midas::allocator::{self, debug::{self, Violation, ViolationKind}};
+use core::{panic::PanicInfo, ptr};
+
+entry_point!(main);
+
+static LAST_VIOLATION: spin::Mutex<Option<Violation>> = spin::Mutex::new(None);
+// more than the side table holds
+static mut HELD: [*mut u8; 4200] = [ptr::null_mut(); 4200];
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::{self, GlobalFrameAllocator};
+    use x86_64::VirtAddr;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    // record violations instead of panicking, so the tests can look at them
+    debug::set_violation_handler(record_violation);
+
+    test_main();
+    loop {}
+}
+
+fn record_violation(violation: &Violation) {
+    *LAST_VIOLATION.lock() = Some(*violation);
+}
+
+fn take_violation() -> Option<Violation> {
+    LAST_VIOLATION.lock().take()
+}
+
+#[test_case]
+fn clean_allocation_reports_nothing() {
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        ptr::write_bytes(ptr, 0x42, layout.size());
+        dealloc(ptr, layout);
+    }
+    assert!(take_violation().is_none());
+}
+
+#[test_case]
+fn double_free_is_caught() {
+    let layout = Layout::from_size_align(48, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        dealloc(ptr, layout);
+        dealloc(ptr, layout);
+    }
+
+    let violation = take_violation().expect("double free went unnoticed");
+    assert_eq!(violation.kind, ViolationKind::DoubleFree);
+    assert!(violation.allocated_at.unwrap().0[0] != 0);
+    assert!(violation.freed_at.is_some());
+}
+
+#[test_case]
+fn mismatched_layout_is_caught() {
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        dealloc(ptr, Layout::from_size_align(32, 8).unwrap());
+    }
+
+    let violation = take_violation().expect("mismatched layout went unnoticed");
+    assert_eq!(violation.kind, ViolationKind::LayoutMismatch);
+    assert_eq!(violation.allocated_layout, Some(layout));
+}
+
+#[test_case]
+fn overflow_into_redzone_is_caught() {
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        ptr.add(layout.size()).write(0);
+        dealloc(ptr, layout);
+    }
+
+    let violation = take_violation().expect("overflow went unnoticed");
+    assert_eq!(violation.kind, ViolationKind::RedzoneCorrupted);
+}
+
+#[test_case]
+fn freed_memory_is_poisoned() {
+    let layout = Layout::from_size_align(256, 8).unwrap();
+    unsafe {
+        let ptr = alloc(layout);
+        ptr::write_bytes(ptr, 0x42, layout.size());
+        dealloc(ptr, layout);
+
+        // the start may hold the allocator's free list node, the rest keeps the poison
+        for offset in 64..layout.size() {
+            assert_eq!(ptr.add(offset).read_volatile(), 0xdd);
+        }
+    }
+    assert!(take_violation().is_none());
+}
+
+#[test_case]
+fn old_frees_make_room_again() {
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    let held = unsafe { &mut HELD[..3000] };
+    unsafe {
+        for ptr in held.iter_mut() {
+            *ptr = alloc(layout);
+        }
+        for &ptr in held.iter() {
+            dealloc(ptr, layout);
+        }
+    }
+    assert!(take_violation().is_none());
+    assert!(debug::stats().freed <= 1024);
+
+    // the newest frees are still known
+    unsafe { dealloc(held[2999], layout) };
+    assert_eq!(take_violation().map(|violation| violation.kind), Some(ViolationKind::DoubleFree));
+}
+
+// has to come last, once allocations went untracked unknown pointers aren't reported anymore
+#[test_case]
+fn full_table_is_reported() {
+    let layout = Layout::from_size_align(8, 8).unwrap();
+    let held = unsafe { &mut HELD[..] };
+    unsafe {
+        for ptr in held.iter_mut() {
+            *ptr = alloc(layout);
+        }
+    }
+
+    let violation = take_violation().expect("full side table went unnoticed");
+    assert_eq!(violation.kind, ViolationKind::TableFull);
+    assert!(debug::stats().untracked > 0);
+
+    unsafe {
+        for &ptr in held.iter() {
+            dealloc(ptr, layout);
+        }
+    }
+    assert!(take_violation().is_none());
+}
@@ -0,0 +1,160 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, fault::{self, FaultKind}, gdt, interrupts::{self, IrqError, IrqReturn}, memory};
+use core::{arch::asm, panic::PanicInfo, sync::atomic::{AtomicU64, Ordering}};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+    gdt::init_stacks(&mut mapper, &mut frame_allocator)
+        .expect("exception stack initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn exception_stacks_have_guard_pages() {
+    let names = ["double fault", "page fault", "nested page fault", "nested page fault", "NMI", "machine check"];
+    assert_eq!(names.len(), gdt::IST_STACK_COUNT);
+    for (index, name) in names.iter().enumerate() {
+        let guard_start = VirtAddr::new(gdt::IST_STACKS_START + index as u64 * (gdt::IST_STACK_PAGES + 1) * 4096);
+        let guard = gdt::stack_guard_hit(guard_start + 8u64).expect("no guard page");
+        assert_eq!(guard.name, *name);
+
+        // the guard page isn't mapped, the stack above it is
+        let fault = fault::catch(|| unsafe {
+            core::ptr::read_volatile(guard_start.as_ptr::<u64>());
+        })
+        .expect_err("the guard page is mapped");
+        assert_eq!(fault.kind, FaultKind::PageFault);
+        assert!(fault::catch(|| unsafe {
+            core::ptr::read_volatile((guard.stack_top - 8u64).as_ptr::<u64>());
+        })
+        .is_ok());
+    }
+}
+
+#[test_case]
+fn page_faults_give_their_stack_back() {
+    for _ in 0..gdt::PAGE_FAULT_STACKS + 1 {
+        let fault = fault::catch(|| unsafe {
+            core::ptr::read_volatile(0xdead_beef_000 as *const u64);
+        })
+        .expect_err("reading unmapped memory didn't fault");
+        assert_eq!(fault.kind, FaultKind::PageFault);
+        assert_eq!(gdt::page_fault_depth(), 0);
+    }
+}
+
+#[test_case]
+fn nmis_are_counted() {
+    let before = interrupts::nmi_count();
+    unsafe { asm!("int 2") };
+    assert_eq!(interrupts::nmi_count(), before + 1);
+}
+
+static FIRST_CALLS: AtomicU64 = AtomicU64::new(0);
+static SECOND_CALLS: AtomicU64 = AtomicU64::new(0);
+
+fn first(_vector: u8) -> IrqReturn {
+    FIRST_CALLS.fetch_add(1, Ordering::SeqCst);
+    IrqReturn::Handled
+}
+
+fn second(_vector: u8) -> IrqReturn {
+    SECOND_CALLS.fetch_add(1, Ordering::SeqCst);
+    IrqReturn::NotHandled
+}
+
+#[test_case]
+fn registrations_are_checked() {
+    assert_eq!(interrupts::register_irq(16, "test", first), Err(IrqError::InvalidLine(16)));
+    assert_eq!(interrupts::register_vector(14, "test", first), Err(IrqError::InvalidVector(14)));
+    assert_eq!(interrupts::register_vector(64, "test", first), Err(IrqError::InvalidVector(64)));
+
+    let ids: [_; 4] = core::array::from_fn(|_| interrupts::register_vector(50, "test", first).unwrap());
+    assert_eq!(interrupts::register_vector(50, "test", first), Err(IrqError::VectorFull(50)));
+    for id in ids {
+        assert_eq!(interrupts::unregister(id), Ok(()));
+    }
+}
+
+#[test_case]
+fn stale_handler_ids_are_rejected() {
+    let old = interrupts::register_vector(51, "old", first).unwrap();
+    assert_eq!(interrupts::unregister(old), Ok(()));
+    assert_eq!(interrupts::unregister(old), Err(IrqError::NotRegistered));
+
+    // takes over the slot the old handler had
+    let new = interrupts::register_vector(51, "new", first).unwrap();
+    assert_eq!(interrupts::unregister(old), Err(IrqError::NotRegistered));
+    assert_eq!(interrupts::irq_stats()[51 - 32].handlers, ["new"]);
+    assert_eq!(interrupts::unregister(new), Ok(()));
+}
+
+#[test_case]
+fn shared_vectors_run_every_handler() {
+    let ids = [
+        interrupts::register_vector(52, "first", first).unwrap(),
+        interrupts::register_vector(52, "second", second).unwrap(),
+    ];
+    unsafe { asm!("int 52") };
+
+    assert_eq!(FIRST_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(SECOND_CALLS.load(Ordering::SeqCst), 1);
+    let stat = &interrupts::irq_stats()[52 - 32];
+    assert_eq!(stat.count, 1);
+    assert_eq!(stat.unhandled, 0);
+    assert_eq!(stat.handlers, ["first", "second"]);
+
+    for id in ids {
+        interrupts::unregister(id).unwrap();
+    }
+    unsafe { asm!("int 52") };
+    assert_eq!(interrupts::irq_stats()[52 - 32].unhandled, 1);
+}
+
+#[test_case]
+fn registering_unmasks_the_line() {
+    let masks = || x86_64::instructions::interrupts::without_interrupts(|| unsafe { interrupts::PICS.lock().read_masks() });
+    let masked = |line: u8| {
+        let [primary, secondary] = masks();
+        if line < 8 { primary & 1 << line != 0 } else { secondary & 1 << (line - 8) != 0 }
+    };
+
+    // nothing drives these lines in qemu
+    for line in [5, 10] {
+        let id = interrupts::register_irq(line, "test", second).unwrap();
+        assert!(!masked(line));
+        if line >= 8 {
+            assert!(!masked(2), "the cascade line is still masked");
+        }
+
+        interrupts::unregister(id).unwrap();
+        assert!(masked(line));
+    }
+    assert!(!masked(interrupts::InterruptIndex::Timer.irq_line()));
+}
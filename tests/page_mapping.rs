@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory::{self, mapping::{self, MapError, MapFlags, MmioRegion, PageBuffer}}};
+use core::panic::PanicInfo;
+use x86_64::{structures::paging::{Page, PageTableFlags, Translate, mapper::TranslateResult}, PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+const TEST_AREA: u64 = 0x_7777_0000_0000;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+fn page_flags(addr: VirtAddr) -> Option<PageTableFlags> {
+    match unsafe { memory::active_mapper() }.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    }
+}
+
+#[test_case]
+fn map_and_unmap_fresh_frames() {
+    let start = VirtAddr::new(TEST_AREA);
+    let size = 3 * 4096;
+    let free_before = memory::frame_stats().unwrap().free_frames;
+
+    mapping::map_range(start, size, MapFlags::KERNEL_DATA).expect("mapping failed");
+    let memory = unsafe { core::slice::from_raw_parts_mut(start.as_mut_ptr::<u8>(), size) };
+    assert!(memory.iter().all(|&byte| byte == 0));
+    memory.fill(0x5a);
+
+    let flags = page_flags(start).unwrap();
+    assert!(flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE));
+    assert!(!flags.contains(PageTableFlags::USER_ACCESSIBLE));
+
+    mapping::unmap_range(start, size, true).expect("unmapping failed");
+    assert!(page_flags(start).is_none());
+
+    // page table frames made for the mapping stay around, so don't compare exactly
+    assert!(memory::frame_stats().unwrap().free_frames + 3 >= free_before);
+}
+
+#[test_case]
+fn mapping_twice_fails_cleanly() {
+    let start = VirtAddr::new(TEST_AREA + 0x10_0000);
+    mapping::map_range(start + 4096u64, 4096, MapFlags::KERNEL_DATA).unwrap();
+
+    let result = mapping::map_range(start, 2 * 4096, MapFlags::KERNEL_DATA);
+    assert_eq!(result, Err(MapError::AlreadyMapped(start + 4096u64)));
+
+    // the first page was rolled back, the one that was there already stays
+    assert!(page_flags(start).is_none());
+    assert!(page_flags(start + 4096u64).is_some());
+    mapping::unmap_range(start + 4096u64, 4096, true).unwrap();
+}
+
+#[test_case]
+fn unmapping_unmapped_pages_is_an_error() {
+    let start = VirtAddr::new(TEST_AREA + 0x20_0000);
+    assert_eq!(mapping::unmap_range(start, 4096, false), Err(MapError::NotMapped(start)));
+}
+
+#[test_case]
+fn mmio_region_sees_the_device_memory() {
+    let vga = PhysAddr::new(0xb8000);
+    let mut region = unsafe { MmioRegion::map(vga, 80 * 25 * 2) }.expect("mmio mapping failed");
+    let direct = (memory::physical_memory_offset() + vga.as_u64()).as_ptr::<u16>();
+
+    assert_eq!(region.read::<u16>(0), Ok(unsafe { direct.read_volatile() }));
+    assert!(region.read::<u16>(80 * 25 * 2).is_err());
+    assert!(region.write::<u32>(80 * 25 * 2 - 2, 0).is_err());
+
+    let flags = page_flags(region.virt_addr()).unwrap();
+    assert!(flags.contains(PageTableFlags::NO_CACHE));
+}
+
+#[test_case]
+fn page_buffer_is_bounds_checked() {
+    let mut buffer = PageBuffer::new(2).expect("page buffer allocation failed");
+    assert_eq!(buffer.len(), 2 * 4096);
+
+    buffer.write::<u64>(4096 - 4, 0x1122_3344_5566_7788).unwrap();
+    assert_eq!(buffer.read::<u64>(4096 - 4), Ok(0x1122_3344_5566_7788));
+    assert!(buffer.write::<u64>(2 * 4096 - 7, 0).is_err());
+    assert!(buffer.read::<u8>(2 * 4096).is_err());
+
+    let page = Page::<x86_64::structures::paging::Size4KiB>::containing_address(buffer.virt_addr() + 4096u64);
+    let phys = buffer.phys_addr(4096 + 8).unwrap();
+    assert_eq!(unsafe { memory::active_mapper() }.translate_addr(page.start_address() + 8u64), Some(phys));
+}
+
+#[test_case]
+fn translation_handles_huge_pages() {
+    // the bootloader's physical memory mapping may well use huge pages
+    let offset = memory::physical_memory_offset();
+    let phys = unsafe { memory::translate_addr(offset + 0x20_1234u64, offset) };
+    assert_eq!(phys, Some(PhysAddr::new(0x20_1234)));
+}
+
+#[test_case]
+fn large_ranges_use_huge_pages() {
+    let start = VirtAddr::new(TEST_AREA + 0x4000_0000);
+    let size = 4 * 1024 * 1024;
+    let flags = MapFlags { no_execute: true, ..MapFlags::KERNEL_CODE };
+
+    // a second, read only view of the first 4 MiB of physical memory
+    unsafe { mapping::map_large_range_to(start, PhysAddr::new(0), size, flags) }.expect("mapping failed");
+
+    let walk = memory::walk_page_tables(start + 0x20_0010u64);
+    assert_eq!(walk.page_size, 2 * 1024 * 1024);
+    assert_eq!(walk.phys, Some(PhysAddr::new(0x20_0010)));
+    assert_eq!(walk.steps[3].map(|step| step.level), None);
+
+    // a huge page can't be split
+    assert_eq!(mapping::unmap_range(start, 4096, false), Err(MapError::HugePage(start)));
+
+    mapping::unmap_range(start, size, false).expect("unmapping failed");
+    assert!(memory::walk_page_tables(start).phys.is_none());
+}
@@ -0,0 +1,107 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator::{self, HEAP_START}, fault::{self, FaultKind}, memory::{self, protection}};
+use core::panic::PanicInfo;
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+entry_point!(main);
+
+static mut DATA: [u8; 16] = [0; 16];
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    protection::init();
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+// what the walk says about `addr`, every level has to allow writes or execution
+fn access(addr: VirtAddr) -> (bool, bool) {
+    let walk = memory::walk_page_tables(addr);
+    assert!(walk.phys.is_some(), "{:?} isn't mapped", addr);
+
+    let steps = walk.steps.iter().flatten();
+    let writable = steps.clone().all(|step| step.flags.contains(PageTableFlags::WRITABLE));
+    let executable = steps.clone().all(|step| !step.flags.contains(PageTableFlags::NO_EXECUTE));
+    (writable, executable)
+}
+
+#[test_case]
+fn everything_supported_is_enabled() {
+    let supported = protection::supported();
+    let enabled = protection::enabled();
+    assert_eq!(enabled, supported);
+    assert!(enabled.write_protect);
+}
+
+#[test_case]
+fn kernel_text_is_read_only() {
+    let text = VirtAddr::from_ptr(access as *const u8);
+    assert_eq!(access(text), (false, true));
+
+    let result = fault::catch(|| unsafe { text.as_mut_ptr::<u8>().write_volatile(0xc3) });
+    assert_eq!(result.unwrap_err().kind, FaultKind::PageFault);
+}
+
+#[test_case]
+fn data_and_heap_are_not_executable() {
+    if !protection::enabled().no_execute {
+        return;
+    }
+
+    let data = VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(DATA) });
+    assert_eq!(access(data), (true, false));
+    assert_eq!(access(VirtAddr::new(HEAP_START as u64)), (true, false));
+
+    let value = Box::new(0u64);
+    assert_eq!(access(VirtAddr::from_ptr(&*value)), (true, false));
+    assert_eq!(access(memory::physical_memory_offset()).1, false);
+}
+
+#[test_case]
+fn executing_the_heap_faults() {
+    if !protection::enabled().no_execute {
+        return;
+    }
+
+    // a lone `ret`
+    let code: Vec<u8> = alloc::vec![0xc3; 16];
+    let function: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    let result = fault::catch(|| function());
+    assert_eq!(result.unwrap_err().kind, FaultKind::PageFault);
+}
+
+#[test_case]
+fn audit_finds_no_kernel_memory() {
+    let text = VirtAddr::from_ptr(access as *const u8).as_u64();
+    let data = VirtAddr::from_ptr(unsafe { core::ptr::addr_of!(DATA) }).as_u64();
+    let heap = HEAP_START as u64;
+
+    for mapping in protection::writable_executable_mappings() {
+        let range = mapping.start.as_u64()..mapping.start.as_u64() + mapping.size;
+        assert!(!range.contains(&text) && !range.contains(&data) && !range.contains(&heap),
+            "{:#x} is writable and executable", mapping.start.as_u64());
+    }
+}
@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator::{self, slab::{CacheBox, ObjectCache}}, memory};
+use core::panic::PanicInfo;
+use x86_64::{structures::paging::{PageTable, Translate}, VirtAddr};
+
+entry_point!(main);
+
+static SMALL_OBJECTS: ObjectCache<[u64; 4]> = ObjectCache::new("test-small");
+static BIG_OBJECTS: ObjectCache<[u8; 512]> = ObjectCache::new("test-big");
+static PAGE_TABLES: ObjectCache<PageTable> = ObjectCache::new("test-page-tables");
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn freed_objects_are_reused() {
+    let first = SMALL_OBJECTS.alloc([1, 2, 3, 4]).expect("slab allocation failed");
+    let address = CacheBox::as_ptr(&first) as usize;
+    assert_eq!(*first, [1, 2, 3, 4]);
+    drop(first);
+
+    let second = SMALL_OBJECTS.alloc([5, 6, 7, 8]).expect("slab allocation failed");
+    assert_eq!(CacheBox::as_ptr(&second) as usize, address);
+    assert_eq!(*second, [5, 6, 7, 8]);
+}
+
+#[test_case]
+fn slab_occupancy_is_tracked() {
+    let per_slab = BIG_OBJECTS.stats().objects_per_slab;
+    let objects: Vec<_> = (0..per_slab + 1)
+        .map(|i| BIG_OBJECTS.alloc([i as u8; 512]).expect("slab allocation failed"))
+        .collect();
+
+    let stats = BIG_OBJECTS.stats();
+    assert_eq!(stats.slabs, 2);
+    assert_eq!(stats.full_slabs, 1);
+    assert_eq!(stats.objects_in_use, per_slab + 1);
+
+    for (i, object) in objects.iter().enumerate() {
+        assert!(object.iter().all(|&byte| byte == i as u8));
+    }
+    drop(objects);
+    assert_eq!(BIG_OBJECTS.stats().objects_in_use, 0);
+}
+
+#[test_case]
+fn empty_slabs_are_released() {
+    // allocated up front, growing the heap would use frames too
+    let mut objects = Vec::with_capacity(100);
+
+    BIG_OBJECTS.shrink();
+    let free_before = memory::frame_stats().unwrap().free_frames;
+
+    for _ in 0..100 {
+        objects.push(BIG_OBJECTS.alloc([0; 512]).expect("slab allocation failed"));
+    }
+    assert!(memory::frame_stats().unwrap().free_frames < free_before);
+
+    // all but one empty slab go back as soon as they empty out
+    objects.clear();
+    assert!(BIG_OBJECTS.stats().slabs <= 1);
+
+    BIG_OBJECTS.shrink();
+    assert_eq!(BIG_OBJECTS.stats().slabs, 0);
+    assert_eq!(memory::frame_stats().unwrap().free_frames, free_before);
+}
+
+#[test_case]
+fn page_table_cache_is_page_aligned() {
+    let table = PAGE_TABLES.alloc(PageTable::new()).expect("slab allocation failed");
+    let virt = VirtAddr::new(CacheBox::as_ptr(&table) as u64);
+    assert!(virt.is_aligned(4096u64));
+    assert!(table.iter().all(|entry| entry.is_unused()));
+
+    let mapper = unsafe { memory::active_mapper() };
+    assert_eq!(mapper.translate_addr(virt), Some(CacheBox::phys_addr(&table)));
+}
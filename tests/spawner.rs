@@ -0,0 +1,257 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory, task::{self, executor::Executor, local, registry, JoinError, Priority, Task, TaskState}};
+use core::{future::Future, panic::PanicInfo, pin::Pin, sync::atomic::{AtomicUsize, Ordering}, task::{Context, Poll}};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn join_handles_return_the_output() {
+    let mut executor = Executor::new();
+    let handle = executor.spawner().spawn(async { 6 * 7 });
+    assert!(!handle.is_finished());
+
+    let result = Arc::new(AtomicUsize::new(0));
+    let seen = result.clone();
+    executor.spawn(Task::new(async move {
+        seen.store(handle.await.unwrap(), Ordering::SeqCst);
+    }));
+
+    executor.run_until_idle();
+    assert_eq!(result.load(Ordering::SeqCst), 42);
+}
+
+#[test_case]
+fn tasks_spawn_tasks_while_running() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let outer_count = count.clone();
+    let outer = executor.spawner().spawn(async move {
+        let mut handles = alloc::vec::Vec::new();
+        for index in 0..10 {
+            let count = outer_count.clone();
+            handles.push(spawner.spawn(async move {
+                count.fetch_add(1, Ordering::SeqCst);
+                index
+            }));
+        }
+
+        let mut sum = 0;
+        for handle in handles {
+            sum += handle.await.unwrap();
+        }
+        sum
+    });
+
+    executor.run_until_idle();
+    assert_eq!(count.load(Ordering::SeqCst), 10);
+    assert!(outer.is_finished());
+}
+
+#[test_case]
+fn dropped_handles_detach() {
+    let mut executor = Executor::new();
+    let ran = Arc::new(AtomicUsize::new(0));
+    let seen = ran.clone();
+
+    drop(executor.spawner().spawn(async move {
+        seen.store(1, Ordering::SeqCst);
+    }));
+    executor.run_until_idle();
+    assert_eq!(ran.load(Ordering::SeqCst), 1);
+}
+
+// never finishes by itself
+struct Forever;
+
+impl Future for Forever {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, _context: &mut Context) -> Poll<usize> {
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn aborting_wakes_joiners() {
+    let mut executor = Executor::new();
+    let stuck = executor.spawner().spawn_named("stuck", Forever);
+    let abort = stuck.abort_handle();
+
+    let result = Arc::new(spin::Mutex::new(None));
+    let seen = result.clone();
+    executor.spawn(Task::new(async move {
+        *seen.lock() = Some(stuck.await);
+    }));
+    executor.run_until_idle();
+
+    let stats = registry::tasks().into_iter().find(|task| task.id == abort.id()).expect("task isn't listed");
+    assert_eq!(stats.name.as_deref(), Some("stuck"));
+    assert_eq!(stats.state, TaskState::Waiting);
+    assert_eq!(stats.polls, 1);
+    assert!(result.lock().is_none());
+
+    abort.abort();
+    executor.run_until_idle();
+    assert_eq!(*result.lock(), Some(Err(JoinError::Aborted)));
+    assert!(registry::tasks().iter().all(|task| task.id != abort.id()));
+}
+
+#[test_case]
+fn task_locals_belong_to_one_task() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    assert!(!local::set("outside", 1u32));
+
+    let first = spawner.spawn(async {
+        local::set("value", 1u32);
+        task::yield_now().await;
+        local::with("value", |value: &mut u32| *value += 10);
+        local::get::<u32>("value")
+    });
+    let second = spawner.spawn(async {
+        let before = local::get::<u32>("value");
+        local::set("value", 2u32);
+        task::yield_now().await;
+        (before, local::get::<u32>("value"), local::get::<u64>("value"))
+    });
+
+    let results = Arc::new(spin::Mutex::new(None));
+    let seen = results.clone();
+    executor.spawn(Task::new(async move {
+        *seen.lock() = Some((first.await.unwrap(), second.await.unwrap()));
+    }));
+    executor.run_until_idle();
+
+    assert_eq!(*results.lock(), Some((Some(11), (None, Some(2), None))));
+}
+
+// wakes itself many times on its first poll, then waits forever
+struct Noisy {
+    polled: bool,
+}
+
+impl Future for Noisy {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if !self.polled {
+            self.polled = true;
+            for _ in 0..500 {
+                context.waker().wake_by_ref();
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn wakeup_bursts_dont_overflow() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+    let handles: alloc::vec::Vec<_> = (0..2000).map(|_| spawner.spawn(Noisy { polled: false })).collect();
+    executor.run_until_idle();
+
+    // woken 500 times, polled once more for all of them
+    let ids: alloc::vec::Vec<u64> = handles.iter().map(|handle| handle.abort_handle().id()).collect();
+    let noisy: alloc::vec::Vec<_> = registry::tasks().into_iter().filter(|task| ids.contains(&task.id)).collect();
+    assert_eq!(noisy.len(), 2000);
+    assert!(noisy.iter().all(|task| task.polls == 2));
+
+    handles.iter().for_each(|handle| handle.abort());
+    executor.run_until_idle();
+    assert!(handles.iter().all(|handle| handle.is_finished()));
+}
+
+#[test_case]
+fn higher_priorities_go_first() {
+    let mut executor = Executor::new();
+    let order = Arc::new(spin::Mutex::new(alloc::vec::Vec::new()));
+
+    for priority in [Priority::Background, Priority::Ui, Priority::Input] {
+        let order = order.clone();
+        executor.spawn(Task::new(async move { order.lock().push(priority) }).with_priority(priority));
+    }
+    executor.run_until_idle();
+
+    assert_eq!(*order.lock(), [Priority::Input, Priority::Ui, Priority::Background]);
+}
+
+static HOG_POLLS: AtomicUsize = AtomicUsize::new(0);
+
+#[test_case]
+fn busy_tasks_dont_starve_the_rest() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+
+    // wakes itself every poll
+    let hog = spawner.spawn_with_priority(Priority::Input, async {
+        for _ in 0..1000 {
+            HOG_POLLS.fetch_add(1, Ordering::SeqCst);
+            task::yield_now().await;
+        }
+    });
+    let starved = spawner.spawn_with_priority(Priority::Background, async {
+        HOG_POLLS.load(Ordering::SeqCst)
+    });
+
+    let seen = Arc::new(AtomicUsize::new(usize::MAX));
+    let result = seen.clone();
+    executor.spawn(Task::new(async move {
+        result.store(starved.await.unwrap(), Ordering::SeqCst);
+    }));
+    let hog_id = hog.abort_handle().id();
+    executor.run_until_idle();
+
+    // the background task got its turn in the first round
+    assert!(seen.load(Ordering::SeqCst) < 100);
+    assert!(hog.is_finished());
+    assert!(registry::tasks().iter().all(|task| task.id != hog_id));
+}
+
+#[test_case]
+fn poll_time_is_counted() {
+    let mut executor = Executor::new();
+    let stuck = executor.spawner().spawn(Noisy { polled: false });
+    executor.run_until_idle();
+
+    let id = stuck.abort_handle().id();
+    let stats = registry::tasks().into_iter().find(|task| task.id == id).expect("task isn't listed");
+    assert_eq!(stats.priority, Priority::Ui);
+    assert!(stats.poll_cycles > 0);
+    assert!(stats.max_poll_cycles <= stats.poll_cycles);
+
+    stuck.abort();
+    executor.run_until_idle();
+}
@@ -0,0 +1,167 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, sync::Arc};
+use bootloader::{entry_point, BootInfo};
+use midas::{
+    allocator,
+    block::{ata::{AtaDisk, Bus, Drive}, BlockDevice, SECTOR_SIZE},
+    memory::{self, address_space::{self, AddressSpace, USER_SPACE_START}, mapping::MapFlags, swap::{self, SwapError}},
+};
+use core::panic::PanicInfo;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+// the disk image the test runner attaches next to the boot disk
+fn swap_disk() -> AtaDisk {
+    AtaDisk::open(Bus::Primary, Drive::Slave).expect("no swap disk")
+}
+
+fn used_slots() -> usize {
+    swap::stats().map_or(0, |stats| stats.used)
+}
+
+// a space with `pages` pages at the start of user space, each one filled with its number
+fn filled_space(pages: u64) -> Arc<AddressSpace> {
+    let space = Arc::new(AddressSpace::new().expect("no address space"));
+    let start = VirtAddr::new(USER_SPACE_START);
+    space.map(start, (pages * 4096) as usize, MapFlags::KERNEL_DATA).unwrap();
+    for page in 0..pages {
+        space.copy_to(start + page * 4096, &[page as u8 + 1; 4096]).unwrap();
+    }
+    space
+}
+
+fn check_pages(space: &AddressSpace, pages: u64) {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let mut buffer = [0u8; 4096];
+    for page in 0..pages {
+        space.copy_from(start + page * 4096, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&byte| byte == page as u8 + 1), "page {} changed", page);
+    }
+}
+
+#[test_case]
+fn disk_reads_back_what_was_written() {
+    let mut disk = swap_disk();
+    assert!(disk.sector_count() > 0);
+
+    let mut data = [0u8; 2 * SECTOR_SIZE];
+    for (index, byte) in data.iter_mut().enumerate() {
+        *byte = index as u8 ^ 0x5a;
+    }
+    disk.write(100, &data).unwrap();
+
+    let mut buffer = [0u8; 2 * SECTOR_SIZE];
+    disk.read(100, &mut buffer).unwrap();
+    assert_eq!(&buffer[..], &data[..]);
+    assert!(disk.read(disk.sector_count(), &mut buffer).is_err());
+}
+
+#[test_case]
+fn swapping_needs_swapon() {
+    let space = filled_space(1);
+    assert_eq!(space.swap_out(1), Err(SwapError::NotOn));
+
+    // the image keeps its contents between runs, so wipe the signature first
+    let mut disk = swap_disk();
+    disk.write(0, &[0u8; SECTOR_SIZE]).unwrap();
+    assert_eq!(swap::swapon(Box::new(swap_disk())), Err(SwapError::NoSignature));
+
+    let slots = swap::format(&mut disk).unwrap();
+    assert_eq!(slots as u64, disk.sector_count() / 8 - 1);
+    assert_eq!(swap::swapon(Box::new(swap_disk())), Ok(slots));
+    assert_eq!(swap::swapon(Box::new(swap_disk())), Err(SwapError::AlreadyOn));
+}
+
+#[test_case]
+fn swapped_pages_come_back_on_access() {
+    let space = filled_space(8);
+    let before = memory::frame_stats().unwrap().free_frames;
+
+    assert_eq!(space.swap_out(8), Ok(8));
+    assert_eq!(space.resident_pages(), 0);
+    assert_eq!(space.swapped_pages(), 8);
+    assert_eq!(used_slots(), 8);
+    assert!(memory::frame_stats().unwrap().free_frames > before);
+
+    // a fault reads the page in
+    let last = VirtAddr::new(USER_SPACE_START + 7 * 4096);
+    address_space::switch_to(Some(&space));
+    assert_eq!(unsafe { last.as_ptr::<u8>().read_volatile() }, 8);
+    address_space::switch_to(None);
+    assert_eq!(space.resident_pages(), 1);
+
+    check_pages(&space, 8);
+    assert_eq!(space.swapped_pages(), 0);
+    assert_eq!(used_slots(), 0);
+}
+
+#[test_case]
+fn used_pages_get_another_round() {
+    let start = VirtAddr::new(USER_SPACE_START);
+    let space = filled_space(2);
+
+    address_space::switch_to(Some(&space));
+    unsafe { start.as_ptr::<u8>().read_volatile() };
+    address_space::switch_to(None);
+
+    assert_eq!(space.swap_out(1), Ok(1));
+    assert!(space.translate(start).is_some());
+    assert_eq!(space.translate(start + 4096u64), None);
+    check_pages(&space, 2);
+}
+
+#[test_case]
+fn forks_and_teardown_release_slots() {
+    let parent = filled_space(4);
+    assert_eq!(parent.swap_out(4), Ok(4));
+
+    let child = parent.fork().expect("fork failed");
+    assert_eq!(used_slots(), 0);
+    check_pages(&child, 4);
+
+    assert_eq!(child.swap_out(4), Ok(0));
+    drop(parent);
+    assert_eq!(child.swap_out(4), Ok(4));
+    drop(child);
+    assert_eq!(used_slots(), 0);
+}
+
+#[test_case]
+fn swapoff_reads_everything_back() {
+    let space = filled_space(4);
+    assert_eq!(space.swap_out(4), Ok(4));
+
+    swap::swapoff().unwrap();
+    assert!(swap::stats().is_none());
+    assert_eq!(space.resident_pages(), 4);
+    check_pages(&space, 4);
+    assert_eq!(swap::swapoff(), Err(SwapError::NotOn));
+}
@@ -0,0 +1,312 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec::Vec};
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, memory, task::{executor::Executor, sync::{mpsc, oneshot, Mutex, Notify, RwLock, Semaphore}, Task}};
+use core::{future::Future, panic::PanicInfo, sync::atomic::{AtomicUsize, Ordering}};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn bounded_senders_wait_for_room() {
+    let mut executor = Executor::new();
+    let (sender, mut receiver) = mpsc::channel(2);
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    let count = sent.clone();
+    let producer = executor.spawner().spawn(async move {
+        for value in 0..5usize {
+            sender.send(value).await.unwrap();
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    executor.run_until_idle();
+    assert_eq!(sent.load(Ordering::SeqCst), 2);
+    assert_eq!(receiver.len(), 2);
+
+    let received = Arc::new(spin::Mutex::new(Vec::new()));
+    let seen = received.clone();
+    executor.spawn(Task::new(async move {
+        while let Some(value) = receiver.recv().await {
+            seen.lock().push(value);
+        }
+    }));
+    executor.run_until_idle();
+
+    assert!(producer.is_finished());
+    assert_eq!(*received.lock(), [0, 1, 2, 3, 4]);
+}
+
+#[test_case]
+fn try_send_works_outside_tasks() {
+    let mut executor = Executor::new();
+    let (sender, mut receiver) = mpsc::channel(1);
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let seen = received.clone();
+    executor.spawn(Task::new(async move {
+        seen.store(receiver.recv().await.unwrap(), Ordering::SeqCst);
+    }));
+    executor.run_until_idle();
+
+    // the way an interrupt handler would
+    assert_eq!(sender.try_send(7), Ok(()));
+    assert_eq!(sender.try_send(8), Err(mpsc::TrySendError::Full(8)));
+    executor.run_until_idle();
+
+    assert_eq!(received.load(Ordering::SeqCst), 7);
+    assert_eq!(sender.try_send(9), Err(mpsc::TrySendError::Closed(9)));
+    assert!(sender.is_closed());
+}
+
+#[test_case]
+fn unbounded_receivers_see_the_close() {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let other = sender.clone();
+    for value in 0..100 {
+        sender.send(value).unwrap();
+    }
+    drop(sender);
+    assert_eq!(receiver.try_recv(), Ok(0));
+    drop(other);
+
+    let mut executor = Executor::new();
+    let total = Arc::new(AtomicUsize::new(0));
+    let seen = total.clone();
+    executor.spawn(Task::new(async move {
+        while let Some(value) = receiver.recv().await {
+            seen.fetch_add(value, Ordering::SeqCst);
+        }
+        assert_eq!(receiver.try_recv(), Err(mpsc::TryRecvError::Closed));
+    }));
+    executor.run_until_idle();
+    assert_eq!(total.load(Ordering::SeqCst), (1..100).sum());
+}
+
+#[test_case]
+fn oneshots_deliver_or_close() {
+    let mut executor = Executor::new();
+    let (sender, receiver) = oneshot::channel();
+    let (dropped, closed) = oneshot::channel::<u32>();
+
+    let results = Arc::new(spin::Mutex::new(None));
+    let seen = results.clone();
+    executor.spawn(Task::new(async move {
+        *seen.lock() = Some((receiver.await, closed.await));
+    }));
+    executor.run_until_idle();
+    assert!(results.lock().is_none());
+
+    assert_eq!(sender.send(3u32), Ok(()));
+    drop(dropped);
+    executor.run_until_idle();
+    assert_eq!(*results.lock(), Some((Ok(3), Err(oneshot::RecvError::Closed))));
+
+    let (sender, receiver) = oneshot::channel();
+    drop(receiver);
+    assert_eq!(sender.send(4), Err(4));
+}
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+
+#[test_case]
+fn mutex_guards_hold_across_awaits() {
+    let mut executor = Executor::new();
+    let spawner = executor.spawner();
+
+    for _ in 0..4 {
+        spawner.spawn(async {
+            let mut count = COUNTER.lock().await;
+            let before = *count;
+            midas::task::yield_now().await;
+            *count = before + 1;
+        });
+    }
+    executor.run_until_idle();
+
+    assert_eq!(*COUNTER.try_lock().expect("mutex is still locked"), 4);
+}
+
+#[test_case]
+fn waiting_writers_hold_back_new_readers() {
+    let mut executor = Executor::new();
+    let lock = Arc::new(RwLock::new(0usize));
+    let first = lock.try_read().unwrap();
+
+    let writer_lock = lock.clone();
+    let writer = executor.spawner().spawn(async move {
+        *writer_lock.write().await += 1;
+    });
+    executor.run_until_idle();
+    assert!(!writer.is_finished());
+    assert!(lock.try_read().is_none());
+
+    let reader_lock = lock.clone();
+    let reader = executor.spawner().spawn(async move { *reader_lock.read().await });
+    executor.run_until_idle();
+    assert!(!reader.is_finished());
+
+    drop(first);
+    let seen = Arc::new(AtomicUsize::new(0));
+    let result = seen.clone();
+    executor.spawn(Task::new(async move {
+        result.store(reader.await.unwrap(), Ordering::SeqCst);
+    }));
+    executor.run_until_idle();
+
+    assert!(writer.is_finished());
+    assert_eq!(seen.load(Ordering::SeqCst), 1);
+    assert!(lock.try_write().is_some());
+}
+
+#[test_case]
+fn semaphores_limit_tasks() {
+    let mut executor = Executor::new();
+    let semaphore = Arc::new(Semaphore::new(2));
+    let inside = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+
+    for _ in 0..6 {
+        let (semaphore, inside, most) = (semaphore.clone(), inside.clone(), most.clone());
+        executor.spawn(Task::new(async move {
+            let _permit = semaphore.acquire().await;
+            let now = inside.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            midas::task::yield_now().await;
+            inside.fetch_sub(1, Ordering::SeqCst);
+        }));
+    }
+    executor.run_until_idle();
+
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+    assert_eq!(semaphore.available_permits(), 2);
+    semaphore.try_acquire().unwrap().forget();
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+#[test_case]
+fn notifications_are_not_missed() {
+    let mut executor = Executor::new();
+    let notify = Arc::new(Notify::new());
+    let woken = Arc::new(AtomicUsize::new(0));
+
+    // a notify_one before anyone waits is kept
+    notify.notify_one();
+    for _ in 0..3 {
+        let (notify, woken) = (notify.clone(), woken.clone());
+        executor.spawn(Task::new(async move {
+            notify.notified().await;
+            woken.fetch_add(1, Ordering::SeqCst);
+        }));
+    }
+    executor.run_until_idle();
+    assert_eq!(woken.load(Ordering::SeqCst), 1);
+
+    notify.notify_one();
+    executor.run_until_idle();
+    assert_eq!(woken.load(Ordering::SeqCst), 2);
+
+    notify.notify_waiters();
+    executor.run_until_idle();
+    assert_eq!(woken.load(Ordering::SeqCst), 3);
+}
+
+#[test_case]
+fn unlocking_hands_the_mutex_to_the_oldest_waiter() {
+    let mut executor = Executor::new();
+    let mutex = Arc::new(Mutex::new(0usize));
+    let guard = mutex.try_lock().unwrap();
+
+    let waiter_mutex = mutex.clone();
+    let waiter = executor.spawner().spawn(async move { *waiter_mutex.lock().await += 1 });
+    executor.run_until_idle();
+
+    // the waiter is woken but hasn't run, a newcomer still can't get in
+    drop(guard);
+    assert!(mutex.try_lock().is_none());
+    executor.run_until_idle();
+
+    assert!(waiter.is_finished());
+    assert_eq!(*mutex.try_lock().unwrap(), 1);
+}
+
+#[test_case]
+fn given_back_permits_go_to_the_oldest_waiter() {
+    let mut executor = Executor::new();
+    let semaphore = Arc::new(Semaphore::new(1));
+    let permit = semaphore.try_acquire().unwrap();
+
+    let waiter_semaphore = semaphore.clone();
+    let waiter = executor.spawner().spawn(async move {
+        waiter_semaphore.acquire().await.forget();
+    });
+    executor.run_until_idle();
+
+    drop(permit);
+    assert!(semaphore.try_acquire().is_none());
+    executor.run_until_idle();
+
+    assert!(waiter.is_finished());
+    assert_eq!(semaphore.available_permits(), 0);
+}
+
+#[test_case]
+fn dropped_receives_are_not_woken() {
+    let mut executor = Executor::new();
+    let (sender, mut receiver) = mpsc::channel::<u32>(4);
+    let notify = Arc::new(Notify::new());
+
+    let waiting = notify.clone();
+    let task = executor.spawner().spawn(async move {
+        // polls recv once and gives up on it
+        let mut recv = receiver.recv();
+        let first = core::future::poll_fn(|context| {
+            core::task::Poll::Ready(core::pin::Pin::new(&mut recv).poll(context))
+        })
+        .await;
+        assert!(first.is_pending());
+        drop(recv);
+
+        waiting.notified().await;
+        receiver.try_recv()
+    });
+    executor.run_until_idle();
+
+    let id = task.abort_handle().id();
+    let polls = || midas::task::registry::tasks().into_iter().find(|task| task.id == id).unwrap().polls;
+    let before = polls();
+    sender.try_send(5).unwrap();
+    executor.run_until_idle();
+    assert_eq!(polls(), before);
+
+    notify.notify_one();
+    executor.run_until_idle();
+    assert!(task.is_finished());
+}
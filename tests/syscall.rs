@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(midas::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use midas::{allocator, gdt, memory, syscall::{self, EFAULT, ENOSYS, SYS_UPTIME, SYS_WRITE}};
+use core::panic::PanicInfo;
+use x86_64::{
+    registers::model_specific::{Efer, EferFlags, LStar, Star},
+    PrivilegeLevel, VirtAddr,
+};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    midas::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use midas::memory::GlobalFrameAllocator;
+
+    midas::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    unsafe { memory::init_frame_allocator(&boot_info.memory_map, phys_mem_offset) };
+    let mut frame_allocator = GlobalFrameAllocator;
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn user_segments_run_in_ring_3() {
+    let selectors = gdt::selectors();
+    assert_eq!(selectors.code_selector.rpl(), PrivilegeLevel::Ring0);
+    assert_eq!(selectors.data_selector.rpl(), PrivilegeLevel::Ring0);
+    assert_eq!(selectors.user_code_selector.rpl(), PrivilegeLevel::Ring3);
+    assert_eq!(selectors.user_data_selector.rpl(), PrivilegeLevel::Ring3);
+
+    // the order sysret expects
+    assert_eq!(selectors.data_selector.index(), selectors.code_selector.index() + 1);
+    assert_eq!(selectors.user_code_selector.index(), selectors.user_data_selector.index() + 1);
+}
+
+#[test_case]
+fn syscall_msrs_point_at_the_kernel() {
+    let selectors = gdt::selectors();
+    let (user_code, user_data, kernel_code, kernel_data) = Star::read();
+    assert_eq!(user_code, selectors.user_code_selector);
+    assert_eq!(user_data, selectors.user_data_selector);
+    assert_eq!(kernel_code, selectors.code_selector);
+    assert_eq!(kernel_data, selectors.data_selector);
+
+    assert_ne!(LStar::read().as_u64(), 0);
+    assert!(Efer::read().contains(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    assert_ne!(gdt::kernel_stack_top().as_u64(), 0);
+}
+
+#[test_case]
+fn unknown_syscalls_return_enosys() {
+    assert_eq!(syscall::dispatch(2, [0; 5]), ENOSYS);
+    assert_eq!(syscall::dispatch(u64::MAX, [0; 5]), ENOSYS);
+}
+
+#[test_case]
+fn writes_refuse_kernel_memory() {
+    let text = "not for user mode";
+    assert_eq!(syscall::dispatch(SYS_WRITE, [text.as_ptr() as u64, text.len() as u64, 0, 0, 0]), EFAULT);
+    assert_eq!(syscall::dispatch(SYS_WRITE, [u64::MAX, 2, 0, 0, 0]), EFAULT);
+    assert_eq!(syscall::dispatch(SYS_WRITE, [0, 0, 0, 0, 0]), 0);
+}
+
+#[test_case]
+fn uptime_counts_up() {
+    let before = syscall::dispatch(SYS_UPTIME, [0; 5]);
+    // another interrupt may wake the hlt before the timer does
+    while syscall::dispatch(SYS_UPTIME, [0; 5]) == before {
+        x86_64::instructions::hlt();
+    }
+    assert!(syscall::dispatch(SYS_UPTIME, [0; 5]) > before);
+}